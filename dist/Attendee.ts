@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { AttendeeStatus } from "./AttendeeStatus";
+
+export interface Attendee {
+  username: string;
+  status: AttendeeStatus;
+}
@@ -12,4 +12,12 @@ export interface Event {
   location_name: string | null;
   created_at: bigint;
   edited_at: bigint | null;
+  priority: bigint;
+  version: bigint;
+  draft: boolean;
+  recurrence: string | null;
+  owner: string | null;
+  deleted_at: bigint | null;
+  all_day: boolean;
+  timezone: string | null;
 }
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { EventWithDuration } from "./EventWithDuration";
+
+export interface EventExtremes {
+  longest: EventWithDuration | null;
+  shortest: EventWithDuration | null;
+}
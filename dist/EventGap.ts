@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface EventGap {
+  start: bigint;
+  end: bigint;
+  durationSeconds: bigint;
+}
@@ -0,0 +1,24 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface EventWithDuration {
+  id: bigint;
+  title: string;
+  description: string | null;
+  color: string;
+  start_date: bigint;
+  end_date: bigint;
+  location_lng: number | null;
+  location_lat: number | null;
+  location_name: string | null;
+  created_at: bigint;
+  edited_at: bigint | null;
+  priority: bigint;
+  version: bigint;
+  draft: boolean;
+  recurrence: string | null;
+  owner: string | null;
+  deleted_at: bigint | null;
+  all_day: boolean;
+  timezone: string | null;
+  durationSeconds: bigint;
+}
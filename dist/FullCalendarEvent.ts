@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface FullCalendarEvent {
+  id: bigint;
+  title: string;
+  start: string;
+  end: string;
+  color: string;
+  allDay: boolean;
+}
@@ -0,0 +1,11 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface IcsEvent {
+  summary: string;
+  description: string | null;
+  location: string | null;
+  startDate: bigint;
+  endDate: bigint;
+  allDay: boolean;
+  recurrence: string | null;
+}
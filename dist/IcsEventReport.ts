@@ -0,0 +1,10 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { IcsEvent } from "./IcsEvent";
+
+export interface IcsEventReport {
+  index: number;
+  uid: string | null;
+  summary: string | null;
+  event: IcsEvent | null;
+  problems: Array<string>;
+}
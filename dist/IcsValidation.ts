@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { IcsEventReport } from "./IcsEventReport";
+
+export interface IcsValidation {
+  found: number;
+  importable: Array<IcsEventReport>;
+  invalid: Array<IcsEventReport>;
+}
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { IcsEventReport } from "./IcsEventReport";
+
+export interface ImportedEvents {
+  imported: number;
+  skipped: Array<IcsEventReport>;
+}
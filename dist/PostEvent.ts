@@ -9,4 +9,9 @@ export interface PostEvent {
   location_lng: number | null;
   location_lat: number | null;
   location_name: string | null;
+  draft: boolean;
+  recurrence: string | null;
+  owner: string | null;
+  all_day: boolean;
+  timezone: string | null;
 }
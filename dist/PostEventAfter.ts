@@ -0,0 +1,12 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface PostEventAfter {
+  gapSeconds: number;
+  durationSeconds: number;
+  title: string;
+  description: string | null;
+  color: string | null;
+  location_lng: number | null;
+  location_lat: number | null;
+  location_name: string | null;
+}
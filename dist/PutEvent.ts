@@ -9,4 +9,8 @@ export interface PutEvent {
   location_lng: number | null;
   location_lat: number | null;
   location_name: string | null;
+  draft: boolean | null;
+  recurrence: string | null;
+  all_day: boolean | null;
+  timezone: string | null;
 }
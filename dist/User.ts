@@ -3,4 +3,5 @@
 export interface User {
   username: string;
   created_at: bigint;
+  deleted_at: bigint | null;
 }
@@ -0,0 +1,8 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface UserWithEventCount {
+  username: string;
+  created_at: bigint;
+  deleted_at: bigint | null;
+  eventCount: bigint;
+}
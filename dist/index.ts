@@ -0,0 +1,34 @@
+// This file was generated by the export_bindings_index test. Do not edit this file manually.
+export * from "./Attendee";
+export * from "./AttendeeStatus";
+export * from "./BusyHours";
+export * from "./Event";
+export * from "./EventCount";
+export * from "./EventDependencies";
+export * from "./EventExtremes";
+export * from "./EventGap";
+export * from "./EventNeighbors";
+export * from "./EventShift";
+export * from "./EventWithDuration";
+export * from "./EventWithOverlaps";
+export * from "./FullCalendarEvent";
+export * from "./Health";
+export * from "./Heatmap";
+export * from "./IcsEvent";
+export * from "./IcsEventReport";
+export * from "./IcsValidation";
+export * from "./ImportedEvents";
+export * from "./OwnerCount";
+export * from "./PostAttendee";
+export * from "./PostEvent";
+export * from "./PostEventAfter";
+export * from "./PostEventDependency";
+export * from "./PostEventTransfer";
+export * from "./PostUser";
+export * from "./PutEvent";
+export * from "./PutEventPriority";
+export * from "./PutUser";
+export * from "./ShiftedEvents";
+export * from "./TotalTime";
+export * from "./User";
+export * from "./UserWithEventCount";
@@ -0,0 +1,2 @@
+-- Higher priorities are listed first when sorting by priority.
+ALTER TABLE events ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+-- Incremented on every update, used to detect conflicting edits via If-Match.
+ALTER TABLE events ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,17 @@
+-- An event depending on another event may not start before the other one ends.
+CREATE TABLE event_dependencies (
+    event_id INTEGER NOT NULL,
+    depends_on_event_id INTEGER NOT NULL,
+
+    PRIMARY KEY (event_id, depends_on_event_id),
+
+    CONSTRAINT fk_dependent_event
+        FOREIGN KEY (event_id)
+        REFERENCES events (id)
+        ON DELETE CASCADE,
+
+    CONSTRAINT fk_prerequisite_event
+        FOREIGN KEY (depends_on_event_id)
+        REFERENCES events (id)
+        ON DELETE CASCADE
+) STRICT;
@@ -0,0 +1,2 @@
+-- Drafts are hidden from the event lists until they are published.
+ALTER TABLE events ADD COLUMN draft INTEGER NOT NULL DEFAULT 0;
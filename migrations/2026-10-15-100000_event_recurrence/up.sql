@@ -0,0 +1,2 @@
+-- An RFC 5545 RRULE like "FREQ=WEEKLY;COUNT=52", the row itself is the first occurrence.
+ALTER TABLE events ADD COLUMN recurrence TEXT NULL;
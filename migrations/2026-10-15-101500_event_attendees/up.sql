@@ -0,0 +1,18 @@
+CREATE TABLE event_attendees (
+    event_id INTEGER NOT NULL,
+    username TEXT NOT NULL COLLATE NOCASE,
+    -- One of "going", "maybe" or "declined".
+    status TEXT NOT NULL,
+
+    PRIMARY KEY (event_id, username),
+
+    CONSTRAINT fk_attendee_event
+        FOREIGN KEY (event_id)
+        REFERENCES events (id)
+        ON DELETE CASCADE,
+
+    CONSTRAINT fk_attendee_user
+        FOREIGN KEY (username)
+        REFERENCES users (username)
+        ON DELETE CASCADE
+) STRICT;
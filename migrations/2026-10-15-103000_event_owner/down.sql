@@ -0,0 +1,30 @@
+-- sqlite can't drop a column which references another table, so the table is rebuilt without it.
+CREATE TABLE events_new (
+    id INTEGER PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    description TEXT NULL,
+    color TEXT NOT NULL,
+    start_date INTEGER NOT NULL,
+    end_date INTEGER NOT NULL,
+    location_lng REAL NULL,
+    location_lat REAL NULL,
+    location_name TEXT NULL,
+    created_at INTEGER NOT NULL,
+    edited_at INTEGER NULL,
+    priority INTEGER NOT NULL DEFAULT 0,
+    version INTEGER NOT NULL DEFAULT 1,
+    draft INTEGER NOT NULL DEFAULT 0,
+    recurrence TEXT NULL
+) STRICT;
+
+INSERT INTO events_new (
+    id, title, description, color, start_date, end_date, location_lng, location_lat,
+    location_name, created_at, edited_at, priority, version, draft, recurrence
+)
+SELECT
+    id, title, description, color, start_date, end_date, location_lng, location_lat,
+    location_name, created_at, edited_at, priority, version, draft, recurrence
+FROM events;
+
+DROP TABLE events;
+ALTER TABLE events_new RENAME TO events;
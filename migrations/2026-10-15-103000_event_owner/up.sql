@@ -0,0 +1,3 @@
+-- The user who created the event, events created before owners existed have none.
+ALTER TABLE events ADD COLUMN owner TEXT NULL COLLATE NOCASE
+    REFERENCES users (username) ON DELETE SET NULL;
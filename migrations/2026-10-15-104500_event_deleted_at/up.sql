@@ -0,0 +1,2 @@
+-- Deleted events are kept so they can be restored, deleted_at is set when they are deleted.
+ALTER TABLE events ADD COLUMN deleted_at INTEGER NULL;
@@ -0,0 +1,2 @@
+-- Events which last whole days and have no meaningful times.
+ALTER TABLE events ADD COLUMN all_day INTEGER NOT NULL DEFAULT 0;
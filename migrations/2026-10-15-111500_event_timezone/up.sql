@@ -0,0 +1,2 @@
+-- IANA name of the timezone the event was planned in, like Europe/Oslo.
+ALTER TABLE events ADD COLUMN timezone TEXT NULL;
@@ -0,0 +1,2 @@
+-- Deleted users are kept so their events and attendances stay attributed to them.
+ALTER TABLE users ADD COLUMN deleted_at INTEGER NULL;
@@ -0,0 +1,189 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use axum::extract::rejection::JsonRejection;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use ts_rs::TS;
+use utoipa::ToSchema;
+
+use crate::error::{get_conn, Error};
+use crate::event::event_exists;
+use crate::schema::{event_attendees, users};
+use crate::SqlitePool;
+
+/// Whether an attendee is coming to an event.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AttendeeStatus {
+    #[default]
+    Going,
+    Maybe,
+    Declined,
+}
+
+// The status is stored as the same lowercase text it is serialized as.
+impl AttendeeStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            AttendeeStatus::Going => "going",
+            AttendeeStatus::Maybe => "maybe",
+            AttendeeStatus::Declined => "declined",
+        }
+    }
+}
+
+impl FromStr for AttendeeStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        match status {
+            "going" => Ok(AttendeeStatus::Going),
+            "maybe" => Ok(AttendeeStatus::Maybe),
+            "declined" => Ok(AttendeeStatus::Declined),
+            other => Err(anyhow!("Unknown attendee status {other}")),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct Attendee {
+    #[schema(example = "alice")]
+    pub username: String,
+
+    pub status: AttendeeStatus,
+}
+
+#[derive(Debug, Deserialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct PostAttendee {
+    #[schema(example = "alice")]
+    pub username: String,
+
+    /// Defaults to `going`.
+    #[serde(default)]
+    pub status: AttendeeStatus,
+}
+
+/// Get the attendees of an event
+#[utoipa::path(
+    get,
+    path = "/api/event/{id}/attendees",
+    responses(
+        (status = 200, description = "Attendees of the event", body = [Attendee]),
+        (status = 404, description = "Event does not exist"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event"),
+    )
+)]
+pub async fn get_all(
+    Path(id): Path<i64>,
+    Extension(pool): Extension<SqlitePool>,
+) -> Result<Json<Vec<Attendee>>, Error> {
+    let mut conn = get_conn(&pool).await?;
+    if !event_exists(&mut conn, id)? {
+        return Err(Error::NotFound);
+    }
+
+    let rows: Vec<(String, String)> = event_attendees::dsl::event_attendees
+        .filter(event_attendees::dsl::event_id.eq(id))
+        .select((event_attendees::dsl::username, event_attendees::dsl::status))
+        .order_by(event_attendees::dsl::username)
+        .load(&mut *conn)
+        .context("Failed to load attendees")?;
+
+    let attendees = rows
+        .into_iter()
+        .map(|(username, status)| {
+            Ok(Attendee {
+                username,
+                status: status.parse()?,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    debug!(id, count = attendees.len(), "Returning attendees");
+    Ok(Json(attendees))
+}
+
+/// Add a user as an attendee of an event
+#[utoipa::path(
+    post,
+    path = "/api/event/{id}/attendees",
+    request_body = PostAttendee,
+    responses(
+        (status = 201, description = "The attendee was added or their status updated", body = Attendee),
+        (status = 404, description = "The event or user does not exist"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event"),
+    )
+)]
+pub async fn post(
+    Path(id): Path<i64>,
+    Extension(pool): Extension<SqlitePool>,
+    req: Result<Json<PostAttendee>, JsonRejection>,
+) -> Result<(StatusCode, Json<Attendee>), Error> {
+    let Json(req) = req?;
+    let mut conn = get_conn(&pool).await?;
+
+    let attendee = conn.transaction::<_, Error, _>(|conn| {
+        if !event_exists(conn, id)? {
+            return Err(Error::NotFound);
+        }
+
+        // Usernames are case insensitive so we store the name the way the user was created.
+        let username: String = users::dsl::users
+            .filter(users::dsl::username.eq(&req.username))
+            .filter(users::dsl::deleted_at.is_null())
+            .select(users::dsl::username)
+            .first(conn)
+            .optional()
+            .context("Failed to query user")?
+            .ok_or(Error::NotFound)?;
+
+        // Adding someone who is already attending only changes their status.
+        diesel::insert_into(event_attendees::table)
+            .values((
+                event_attendees::dsl::event_id.eq(id),
+                event_attendees::dsl::username.eq(&username),
+                event_attendees::dsl::status.eq(req.status.as_str()),
+            ))
+            .on_conflict((
+                event_attendees::dsl::event_id,
+                event_attendees::dsl::username,
+            ))
+            .do_update()
+            .set(event_attendees::dsl::status.eq(req.status.as_str()))
+            .execute(conn)
+            .context("Failed to insert attendee")?;
+
+        Ok(Attendee {
+            username,
+            status: req.status,
+        })
+    })?;
+
+    debug!(id, ?attendee, "Added attendee");
+    Ok((StatusCode::CREATED, Json(attendee)))
+}
+
+// Foreign keys are not enforced by sqlite unless enabled on every connection, so the attendances
+// of renamed users are moved to their new name by hand.
+pub fn rename_user(conn: &mut SqliteConnection, old: &str, new: &str) -> Result<(), Error> {
+    diesel::update(
+        event_attendees::dsl::event_attendees.filter(event_attendees::dsl::username.eq(old)),
+    )
+    .set(event_attendees::dsl::username.eq(new))
+    .execute(conn)
+    .context("Failed to rename user in attendances")?;
+
+    Ok(())
+}
@@ -0,0 +1,346 @@
+use std::{collections::HashMap, env::VarError, str::FromStr};
+
+use anyhow::{bail, Context};
+use axum::http::HeaderValue;
+use time::{macros::format_description, Date, Time};
+use time_tz::{timezones, PrimitiveDateTimeExt, Tz};
+
+use crate::util::{check_hex_color, normalize_color};
+
+// Settings which can differ between deployments. They are read from environment variables (or the
+// `.env` file) once on startup and shared with the handlers through an `Extension`.
+#[derive(Debug)]
+pub struct Config {
+    // MIN_DURATION_SECONDS: Events shorter than this are rejected when set.
+    pub min_duration_seconds: Option<i64>,
+
+    // MAX_FUTURE_SECONDS: Events starting further than this in the future are rejected when set.
+    pub max_future_seconds: Option<i64>,
+
+    // BLOCKED_WORDS: Comma separated words which may not appear in event titles or descriptions.
+    // WORD_FILTER_MODE: Either `reject` (default) or `mask`.
+    pub word_filter: Option<WordFilter>,
+
+    // COLOR_PALETTE: Comma separated hex colors, when set events may only use these colors.
+    // Stored normalized, see `util::normalize_color`.
+    pub color_palette: Option<Vec<String>>,
+
+    // COLOR_GROUPS: Comma separated groups of the form `name=#color|#color`, e.g.
+    // `work=#0000ff|#3366ff,home=#ff0000`. Events can be filtered by the name of a group.
+    // Colors are stored normalized, see `util::normalize_color`.
+    pub color_groups: HashMap<String, Vec<String>>,
+
+    // WORKING_HOURS: Comma separated days or ranges of days with their hours, e.g.
+    // `mon-fri=09:00-17:00,sat=10:00-14:00`. Free slots are only suggested within these hours.
+    // WORKING_HOURS_TZ: The timezone the hours are in, UTC by default.
+    pub working_hours: Option<WorkingHours>,
+
+    // COORDINATE_DECIMALS: How many decimals coordinates are rounded to, 6 by default.
+    pub coordinate_decimals: u32,
+
+    // MAX_WINDOW_SECONDS: The longest window endpoints like the heatmap or free slots accept, a
+    // little over a year by default.
+    pub max_window_seconds: i64,
+
+    // RESULT_SOFT_LIMIT: Lists of events are cut off after this many events when set, the
+    // response then says that it was truncated and how many events there are in total.
+    pub result_soft_limit: Option<u32>,
+
+    // CORS_ORIGINS: Comma separated origins allowed to call the API from a browser, e.g.
+    // `https://calendar.example.com`. Debug builds allow every origin when it is not set, release
+    // builds refuse to start without it.
+    pub cors_origins: Vec<HeaderValue>,
+
+    // DEFAULT_TIMEZONE: The timezone days and hours are grouped in when a request doesn't give
+    // one, UTC by default. Checked on startup, see `Config::default_timezone`.
+    pub default_timezone: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct WordFilter {
+    // Always lowercase so they can be compared to lowercased input.
+    pub blocked_words: Vec<String>,
+    pub mode: WordFilterMode,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum WordFilterMode {
+    // Requests containing a blocked word are rejected.
+    Reject,
+    // Blocked words are replaced by asterisks.
+    Mask,
+}
+
+#[derive(Debug)]
+pub struct WorkingHours {
+    // Indexed by `Weekday::number_days_from_monday`, days without working hours are `None`.
+    pub days: [Option<(Time, Time)>; 7],
+    // Checked on startup, see `WorkingHours::timezone`.
+    pub timezone: Option<String>,
+}
+
+impl WorkingHours {
+    pub fn timezone(&self) -> Option<&'static Tz> {
+        self.timezone.as_deref().and_then(timezones::get_by_name)
+    }
+
+    // Returns the unix timestamps of the start and end of the working hours on a day, `None` if
+    // it is a day off or the hours don't exist because of a DST change.
+    pub fn on(&self, date: Date) -> Option<(i64, i64)> {
+        let (start, end) = self.days[usize::from(date.weekday().number_days_from_monday())]?;
+        let timestamp = |time: Time| {
+            let date_time = date.with_time(time);
+            let date_time = match self.timezone() {
+                Some(tz) => date_time.assume_timezone(tz).take_first()?,
+                None => date_time.assume_utc(),
+            };
+            Some(date_time.unix_timestamp())
+        };
+
+        Some((timestamp(start)?, timestamp(end)?))
+    }
+
+    // The length of the longest working day, no slot longer than this can ever be found.
+    pub fn longest_seconds(&self) -> i64 {
+        self.days
+            .iter()
+            .flatten()
+            .map(|(start, end)| (*end - *start).whole_seconds())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+// The configuration used when no environment variables are set, mostly useful for tests.
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            min_duration_seconds: None,
+            max_future_seconds: None,
+            word_filter: None,
+            color_palette: None,
+            color_groups: HashMap::new(),
+            working_hours: None,
+            coordinate_decimals: DEFAULT_COORDINATE_DECIMALS,
+            max_window_seconds: DEFAULT_MAX_WINDOW_SECONDS,
+            result_soft_limit: None,
+            cors_origins: Vec::new(),
+            default_timezone: None,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Config {
+            min_duration_seconds: parse_env("MIN_DURATION_SECONDS")?,
+            max_future_seconds: parse_env("MAX_FUTURE_SECONDS")?,
+            word_filter: word_filter_from_env()?,
+            color_palette: color_palette_from_env()?,
+            color_groups: color_groups_from_env()?,
+            working_hours: working_hours_from_env()?,
+            coordinate_decimals: coordinate_decimals_from_env()?,
+            max_window_seconds: parse_env("MAX_WINDOW_SECONDS")?
+                .unwrap_or(DEFAULT_MAX_WINDOW_SECONDS),
+            result_soft_limit: parse_env("RESULT_SOFT_LIMIT")?,
+            cors_origins: cors_origins_from_env()?,
+            default_timezone: timezone_from_env("DEFAULT_TIMEZONE")?,
+        })
+    }
+
+    pub fn default_timezone(&self) -> Option<&'static Tz> {
+        self.default_timezone
+            .as_deref()
+            .and_then(timezones::get_by_name)
+    }
+}
+
+fn word_filter_from_env() -> anyhow::Result<Option<WordFilter>> {
+    let blocked_words = parse_list("BLOCKED_WORDS")?
+        .into_iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>();
+    if blocked_words.is_empty() {
+        return Ok(None);
+    }
+
+    let mode = match std::env::var("WORD_FILTER_MODE").as_deref() {
+        Ok("reject") | Err(VarError::NotPresent) => WordFilterMode::Reject,
+        Ok("mask") => WordFilterMode::Mask,
+        Ok(other) => bail!("WORD_FILTER_MODE should be either reject or mask, not {other}"),
+        Err(e) => bail!("WORD_FILTER_MODE could not be read: {e}"),
+    };
+
+    Ok(Some(WordFilter {
+        blocked_words,
+        mode,
+    }))
+}
+
+fn color_palette_from_env() -> anyhow::Result<Option<Vec<String>>> {
+    let palette = parse_list("COLOR_PALETTE")?;
+    if palette.is_empty() {
+        return Ok(None);
+    }
+
+    let palette = palette
+        .iter()
+        .map(|color| normalize_color(color))
+        .collect::<Vec<_>>();
+    if let Some(color) = palette
+        .iter()
+        .find(|color| check_hex_color(Some(color)).is_err())
+    {
+        bail!("COLOR_PALETTE contains {color} which is not a valid hex color");
+    }
+
+    Ok(Some(palette))
+}
+
+fn color_groups_from_env() -> anyhow::Result<HashMap<String, Vec<String>>> {
+    let mut groups = HashMap::new();
+    for group in parse_list("COLOR_GROUPS")? {
+        let Some((name, colors)) = group.split_once('=') else {
+            bail!("COLOR_GROUPS entries should look like name=#color|#color, not {group}");
+        };
+
+        let colors = colors
+            .split('|')
+            .map(normalize_color)
+            .filter(|color| !color.is_empty())
+            .collect::<Vec<_>>();
+        if let Some(color) = colors
+            .iter()
+            .find(|color| check_hex_color(Some(color)).is_err())
+        {
+            bail!("COLOR_GROUPS contains {color} which is not a valid hex color");
+        }
+
+        groups.insert(name.trim().to_string(), colors);
+    }
+
+    Ok(groups)
+}
+
+pub const DEFAULT_MAX_WINDOW_SECONDS: i64 = 366 * 24 * 60 * 60;
+
+pub const DEFAULT_COORDINATE_DECIMALS: u32 = 6;
+
+// Doubles have about 15 significant digits, with up to 3 of them before the decimal point.
+const MAX_COORDINATE_DECIMALS: u32 = 12;
+
+fn coordinate_decimals_from_env() -> anyhow::Result<u32> {
+    let decimals = parse_env("COORDINATE_DECIMALS")?.unwrap_or(DEFAULT_COORDINATE_DECIMALS);
+    if decimals > MAX_COORDINATE_DECIMALS {
+        bail!("COORDINATE_DECIMALS should be at most {MAX_COORDINATE_DECIMALS}, not {decimals}");
+    }
+
+    Ok(decimals)
+}
+
+fn cors_origins_from_env() -> anyhow::Result<Vec<HeaderValue>> {
+    let origins = parse_list("CORS_ORIGINS")?;
+    if origins.is_empty() && !cfg!(debug_assertions) {
+        bail!("CORS_ORIGINS has to be set in release builds");
+    }
+
+    origins
+        .iter()
+        .map(|origin| {
+            HeaderValue::from_str(origin)
+                .with_context(|| format!("CORS_ORIGINS contains {origin} which is not an origin"))
+        })
+        .collect()
+}
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+fn working_hours_from_env() -> anyhow::Result<Option<WorkingHours>> {
+    let entries = parse_list("WORKING_HOURS")?;
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    let weekday = |name: &str| {
+        WEEKDAYS
+            .iter()
+            .position(|day| day.eq_ignore_ascii_case(name.trim()))
+            .with_context(|| format!("WORKING_HOURS contains {name} which is not a day like mon"))
+    };
+    let time = |value: &str| {
+        Time::parse(value.trim(), format_description!("[hour]:[minute]")).with_context(|| {
+            format!("WORKING_HOURS contains {value} which is not a time like 09:00")
+        })
+    };
+
+    let mut days = [None; 7];
+    for entry in entries {
+        let Some((weekdays, (start, end))) = entry
+            .split_once('=')
+            .and_then(|(weekdays, hours)| Some((weekdays, hours.split_once('-')?)))
+        else {
+            bail!("WORKING_HOURS entries should look like mon-fri=09:00-17:00, not {entry}");
+        };
+
+        let (first, last) = match weekdays.split_once('-') {
+            Some((first, last)) => (weekday(first)?, weekday(last)?),
+            None => (weekday(weekdays)?, weekday(weekdays)?),
+        };
+        let (start, end) = (time(start)?, time(end)?);
+        if first > last || start >= end {
+            bail!("WORKING_HOURS entry {entry} is empty, ranges can't wrap around");
+        }
+
+        for day in &mut days[first..=last] {
+            *day = Some((start, end));
+        }
+    }
+
+    let timezone = timezone_from_env("WORKING_HOURS_TZ")?;
+    Ok(Some(WorkingHours { days, timezone }))
+}
+
+// Reads an optional IANA timezone name, unknown timezones are refused so that the accessors can
+// look them up without failing.
+fn timezone_from_env(name: &str) -> anyhow::Result<Option<String>> {
+    match std::env::var(name) {
+        Ok(timezone) => {
+            if timezones::get_by_name(&timezone).is_none() {
+                bail!("{name} contains unknown timezone {timezone}");
+            }
+            Ok(Some(timezone))
+        }
+        Err(VarError::NotPresent) => Ok(None),
+        Err(e) => bail!("{name} could not be read: {e}"),
+    }
+}
+
+// Parses a comma separated environment variable, empty entries are ignored.
+fn parse_list(name: &str) -> anyhow::Result<Vec<String>> {
+    match std::env::var(name) {
+        Ok(value) => Ok(value
+            .split(',')
+            .map(|entry| entry.trim().to_string())
+            .filter(|entry| !entry.is_empty())
+            .collect()),
+        Err(VarError::NotPresent) => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("{name} could not be read")),
+    }
+}
+
+// Parses an optional environment variable, a variable which is set but can't be parsed is an error
+// so that typos don't silently fall back to the default.
+fn parse_env<T>(name: &str) -> anyhow::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .with_context(|| format!("{name} could not be parsed")),
+        Err(VarError::NotPresent) => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("{name} could not be read")),
+    }
+}
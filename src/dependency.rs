@@ -0,0 +1,287 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Context;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use ts_rs::TS;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::{get_conn, Error};
+use crate::event::{event_exists, Event};
+use crate::schema::{event_dependencies, events};
+use crate::SqlitePool;
+
+/// An event which can only start once the event it depends on has ended.
+#[derive(Debug, Queryable, Insertable)]
+#[diesel(table_name = event_dependencies)]
+pub struct EventDependency {
+    pub event_id: i64,
+    pub depends_on_event_id: i64,
+}
+
+#[derive(Debug, Deserialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct PostEventDependency {
+    /// The event which has to end before this event starts.
+    #[schema(example = 2)]
+    pub depends_on: i64,
+}
+
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct EventDependencies {
+    /// Events which have to end before this event starts.
+    pub blocked_by: Vec<Event>,
+
+    /// Events which can only start once this event has ended.
+    pub blocks: Vec<Event>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct DependencyCheckQuery {
+    /// Reject the change with 409 if an event would start before an event it depends on ends.
+    #[param(example = true)]
+    pub check_dependencies: Option<bool>,
+}
+
+impl DependencyCheckQuery {
+    pub fn enabled(&self) -> bool {
+        self.check_dependencies.unwrap_or(false)
+    }
+}
+
+fn load_dependencies(conn: &mut SqliteConnection, id: i64) -> Result<EventDependencies, Error> {
+    let blocked_by = events::dsl::events
+        .filter(
+            events::dsl::id.eq_any(
+                event_dependencies::dsl::event_dependencies
+                    .filter(event_dependencies::dsl::event_id.eq(id))
+                    .select(event_dependencies::dsl::depends_on_event_id),
+            ),
+        )
+        .filter(events::dsl::deleted_at.is_null())
+        .order_by(events::dsl::start_date)
+        .load::<Event>(conn)
+        .context("Failed to query prerequisite events")?;
+
+    let blocks = events::dsl::events
+        .filter(
+            events::dsl::id.eq_any(
+                event_dependencies::dsl::event_dependencies
+                    .filter(event_dependencies::dsl::depends_on_event_id.eq(id))
+                    .select(event_dependencies::dsl::event_id),
+            ),
+        )
+        .filter(events::dsl::deleted_at.is_null())
+        .order_by(events::dsl::start_date)
+        .load::<Event>(conn)
+        .context("Failed to query dependent events")?;
+
+    Ok(EventDependencies { blocked_by, blocks })
+}
+
+// Adding `id -> depends_on` closes a cycle if `depends_on` already (indirectly) depends on `id`, so
+// we walk the existing dependencies starting from `depends_on` and look for `id`.
+fn creates_cycle(conn: &mut SqliteConnection, id: i64, depends_on: i64) -> Result<bool, Error> {
+    if id == depends_on {
+        return Ok(true);
+    }
+
+    let edges = event_dependencies::dsl::event_dependencies
+        .load::<EventDependency>(conn)
+        .context("Failed to query dependencies")?;
+
+    let mut graph: HashMap<i64, Vec<i64>> = HashMap::new();
+    for edge in edges {
+        graph
+            .entry(edge.event_id)
+            .or_default()
+            .push(edge.depends_on_event_id);
+    }
+
+    let mut visited = HashSet::new();
+    let mut stack = vec![depends_on];
+    while let Some(current) = stack.pop() {
+        if current == id {
+            return Ok(true);
+        }
+
+        if visited.insert(current) {
+            stack.extend(graph.get(&current).into_iter().flatten());
+        }
+    }
+
+    Ok(false)
+}
+
+/// Makes sure the event with `id` does not start before any of its prerequisites end and that none
+/// of the events depending on it start before it ends.
+pub fn check_order(conn: &mut SqliteConnection, id: i64) -> Result<(), Error> {
+    let Some(event) = events::dsl::events
+        .filter(events::dsl::id.eq(id))
+        .filter(events::dsl::deleted_at.is_null())
+        .first::<Event>(conn)
+        .optional()
+        .context("Failed to query event")?
+    else {
+        return Ok(());
+    };
+
+    let prerequisite = events::dsl::events
+        .filter(
+            events::dsl::id.eq_any(
+                event_dependencies::dsl::event_dependencies
+                    .filter(event_dependencies::dsl::event_id.eq(id))
+                    .select(event_dependencies::dsl::depends_on_event_id),
+            ),
+        )
+        .filter(events::dsl::deleted_at.is_null())
+        .filter(events::dsl::end_date.gt(event.start_date))
+        .select(events::dsl::id)
+        .first::<i64>(conn)
+        .optional()
+        .context("Failed to query prerequisite events")?;
+
+    if let Some(prerequisite) = prerequisite {
+        return Err(Error::DependencyViolation {
+            event: id,
+            prerequisite,
+        });
+    }
+
+    let dependent = events::dsl::events
+        .filter(
+            events::dsl::id.eq_any(
+                event_dependencies::dsl::event_dependencies
+                    .filter(event_dependencies::dsl::depends_on_event_id.eq(id))
+                    .select(event_dependencies::dsl::event_id),
+            ),
+        )
+        .filter(events::dsl::deleted_at.is_null())
+        .filter(events::dsl::start_date.lt(event.end_date))
+        .select(events::dsl::id)
+        .first::<i64>(conn)
+        .optional()
+        .context("Failed to query dependent events")?;
+
+    if let Some(dependent) = dependent {
+        return Err(Error::DependencyViolation {
+            event: dependent,
+            prerequisite: id,
+        });
+    }
+
+    Ok(())
+}
+
+/// Get the events an event depends on and the events depending on it
+#[utoipa::path(
+    get,
+    path = "/api/event/{id}/dependencies",
+    responses(
+        (status = 200, description = "Dependencies of the event", body = EventDependencies),
+        (status = 404, description = "Event does not exist"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event"),
+    )
+)]
+pub async fn get_all(
+    Path(id): Path<i64>,
+    Extension(pool): Extension<SqlitePool>,
+) -> Result<Json<EventDependencies>, Error> {
+    let mut conn = get_conn(&pool).await?;
+    if !event_exists(&mut conn, id)? {
+        return Err(Error::NotFound);
+    }
+
+    Ok(Json(load_dependencies(&mut conn, id)?))
+}
+
+/// Make an event depend on another event
+#[utoipa::path(
+    post,
+    path = "/api/event/{id}/dependencies",
+    request_body = PostEventDependency,
+    responses(
+        (status = 201, description = "Added the dependency", body = EventDependencies),
+        (status = 404, description = "One of the events does not exist"),
+        (status = 409, description = "The dependency would create a cycle"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the dependent event"),
+    )
+)]
+pub async fn post(
+    Path(id): Path<i64>,
+    Extension(pool): Extension<SqlitePool>,
+    req: Result<Json<PostEventDependency>, JsonRejection>,
+) -> Result<(StatusCode, Json<EventDependencies>), Error> {
+    let Json(req) = req?;
+    let mut conn = get_conn(&pool).await?;
+
+    let dependencies = conn.transaction::<_, Error, _>(|conn| {
+        if !event_exists(conn, id)? || !event_exists(conn, req.depends_on)? {
+            return Err(Error::NotFound);
+        }
+
+        if creates_cycle(conn, id, req.depends_on)? {
+            return Err(Error::DependencyCycle);
+        }
+
+        diesel::insert_or_ignore_into(event_dependencies::table)
+            .values(&EventDependency {
+                event_id: id,
+                depends_on_event_id: req.depends_on,
+            })
+            .execute(conn)
+            .context("Failed to insert dependency")?;
+
+        load_dependencies(conn, id)
+    })?;
+
+    debug!(id, depends_on = req.depends_on, "Added event dependency");
+    Ok((StatusCode::CREATED, Json(dependencies)))
+}
+
+/// Remove a dependency between two events
+#[utoipa::path(
+    delete,
+    path = "/api/event/{id}/dependencies/{depends_on}",
+    responses(
+        (status = 200, description = "Removed the dependency"),
+        (status = 404, description = "The dependency does not exist"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the dependent event"),
+        ("depends_on" = i64, Path, description = "Identifier of the prerequisite event"),
+    )
+)]
+pub async fn delete(
+    Path((id, depends_on)): Path<(i64, i64)>,
+    Extension(pool): Extension<SqlitePool>,
+) -> Result<(), Error> {
+    let mut conn = get_conn(&pool).await?;
+    let deleted = diesel::delete(
+        event_dependencies::dsl::event_dependencies
+            .filter(event_dependencies::dsl::event_id.eq(id))
+            .filter(event_dependencies::dsl::depends_on_event_id.eq(depends_on)),
+    )
+    .execute(&mut *conn)
+    .context("Failed to delete dependency")?;
+
+    if deleted == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
@@ -1,13 +1,20 @@
+use anyhow::anyhow;
 use axum::{
-    extract::rejection::JsonRejection,
-    http::StatusCode,
+    extract::rejection::{JsonRejection, QueryRejection},
+    http::{header, Request, StatusCode},
+    middleware::Next,
     response::{IntoResponse, Response},
     Json,
 };
+use bb8::PooledConnection;
+use bb8_diesel::DieselConnectionManager;
+use diesel::SqliteConnection;
 use serde_json::json;
 use thiserror::Error;
 use tracing::error;
 
+use crate::SqlitePool;
+
 // The `Error` derive makes it easier to define errors by providing attributes like `error` to
 // provide descriptions of errors and helpers like `#[from]` which make it really easy to create
 // conversions from other errors into this one.
@@ -19,6 +26,12 @@ pub enum Error {
     #[error("Unauthorized")]
     Unauthorized,
 
+    #[error("Forbidden")]
+    Forbidden,
+
+    #[error("The event has been modified since it was last fetched")]
+    PreconditionFailed,
+
     // This is returned in hivefriends when a received field is too long.
     #[error("{field} should not be longer than {maximum_length} characters")]
     TooManyCharacters {
@@ -26,9 +39,86 @@ pub enum Error {
         maximum_length: u64,
     },
 
+    #[error("{field} should be between {minimum} and {maximum}")]
+    OutOfRange {
+        field: &'static str,
+        minimum: i64,
+        maximum: i64,
+    },
+
+    #[error("{field} should be between {minimum} and {maximum}")]
+    CoordinateOutOfRange {
+        field: &'static str,
+        minimum: f64,
+        maximum: f64,
+    },
+
+    #[error("location_lat and location_lng have to be provided together")]
+    IncompleteCoordinates,
+
+    #[error("{0} is not a valid hex color like #87d45d")]
+    InvalidColor(String),
+
+    #[error("The color {0} is not part of the palette")]
+    ColorNotInPalette(String),
+
+    #[error("There is no color group named {0}")]
+    UnknownColorGroup(String),
+
+    #[error("start_date should not be after {latest}")]
+    TooFarInFuture { latest: i64 },
+
+    #[error("The event lasts {duration} seconds but should last at least {minimum} seconds")]
+    DurationTooShort { duration: i64, minimum: i64 },
+
+    #[error("The field {0} contains a blocked word")]
+    BlockedWord(&'static str),
+
+    #[error("{0} is not a known timezone")]
+    InvalidTimezone(String),
+
+    #[error("{0} is not a supported recurrence rule like FREQ=WEEKLY;COUNT=52")]
+    InvalidRecurrence(String),
+
+    #[error("{0} is not a valid date like 2023-08-05")]
+    InvalidDate(String),
+
+    #[error("The body is not an iCalendar file")]
+    InvalidCalendar,
+
+    #[error("The body has to be sent as {0}")]
+    UnsupportedMediaType(&'static str),
+
+    #[error("end_date should not be before start_date")]
+    InvalidDateRange,
+
+    #[error("from should not be after to")]
+    InvalidWindow,
+
+    #[error("The window should not be longer than {maximum} seconds")]
+    WindowTooLong { maximum: i64 },
+
     #[error("A user with that name already exists")]
     UserExists,
 
+    #[error("There is no user named {0}")]
+    UnknownUser(String),
+
+    #[error("The dependency would make the event depend on itself")]
+    DependencyCycle,
+
+    #[error("Event {event} starts before event {prerequisite} it depends on ends")]
+    DependencyViolation { event: i64, prerequisite: i64 },
+
+    #[error("The event overlaps the events {0:?} of the new owner")]
+    OwnerConflict(Vec<i64>),
+
+    #[error("The field {0} is required")]
+    MissingField(&'static str),
+
+    #[error("The title should not be empty")]
+    EmptyTitle,
+
     // This is returned in quotes when a received field is too short (empty).
     #[error("The field {0} is empty")]
     EmptyField(&'static str),
@@ -54,6 +144,131 @@ pub enum Error {
 
     #[error("{0}")]
     JsonRejection(#[from] JsonRejection),
+
+    #[error("{0}")]
+    QueryRejection(#[from] QueryRejection),
+}
+
+// Transactions need to be able to turn errors which occur while beginning or committing them into
+// our error type. We don't expect these to happen so they are simply internal errors.
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Self {
+        Error::InternalError(e.into())
+    }
+}
+
+tokio::task_local! {
+    // The path of the request being handled, errors report it as the instance of the problem.
+    static REQUEST_PATH: String;
+}
+
+// Middleware making the path of the request available to the errors returned while handling it.
+pub async fn track_request_path<B>(req: Request<B>, next: Next<B>) -> Response {
+    let path = req.uri().path().to_string();
+    REQUEST_PATH.scope(path, next.run(req)).await
+}
+
+// Getting a connection fails when every connection of the pool stays in use for too long. That is
+// an internal error of the request rather than a reason to crash the task handling it.
+pub async fn get_conn(
+    pool: &SqlitePool,
+) -> Result<PooledConnection<'_, DieselConnectionManager<SqliteConnection>>, Error> {
+    pool.get()
+        .await
+        .map_err(|e| Error::InternalError(anyhow!("Failed to get a database connection: {e}")))
+}
+
+impl Error {
+    // Errors are returned as RFC 7807 problem details, the type identifies the kind of problem
+    // independent of the message so that clients can tell them apart.
+    // https://www.rfc-editor.org/rfc/rfc7807
+    fn problem_type(&self) -> &'static str {
+        match self {
+            Error::NotFound => "/problems/not-found",
+            Error::Unauthorized => "/problems/unauthorized",
+            Error::Forbidden => "/problems/forbidden",
+            Error::PreconditionFailed => "/problems/precondition-failed",
+            Error::TooManyCharacters { .. } => "/problems/too-many-characters",
+            Error::OutOfRange { .. } => "/problems/out-of-range",
+            Error::CoordinateOutOfRange { .. } => "/problems/coordinate-out-of-range",
+            Error::IncompleteCoordinates => "/problems/incomplete-coordinates",
+            Error::InvalidColor(_) => "/problems/invalid-color",
+            Error::ColorNotInPalette(_) => "/problems/color-not-in-palette",
+            Error::UnknownColorGroup(_) => "/problems/unknown-color-group",
+            Error::DurationTooShort { .. } => "/problems/duration-too-short",
+            Error::TooFarInFuture { .. } => "/problems/too-far-in-future",
+            Error::BlockedWord(_) => "/problems/blocked-word",
+            Error::InvalidTimezone(_) => "/problems/invalid-timezone",
+            Error::InvalidRecurrence(_) => "/problems/invalid-recurrence",
+            Error::InvalidDate(_) => "/problems/invalid-date",
+            Error::InvalidCalendar => "/problems/invalid-calendar",
+            Error::UnsupportedMediaType(_) => "/problems/unsupported-media-type",
+            Error::InvalidWindow => "/problems/invalid-window",
+            Error::WindowTooLong { .. } => "/problems/window-too-long",
+            Error::InvalidDateRange => "/problems/invalid-date-range",
+            Error::UserExists => "/problems/user-exists",
+            Error::UnknownUser(_) => "/problems/unknown-user",
+            Error::DependencyCycle => "/problems/dependency-cycle",
+            Error::DependencyViolation { .. } => "/problems/dependency-violation",
+            Error::OwnerConflict(_) => "/problems/owner-conflict",
+            Error::MissingField(_) => "/problems/missing-field",
+            Error::EmptyTitle => "/problems/empty-title",
+            Error::EmptyField(_) => "/problems/empty-field",
+            Error::EmptyArrayElement(_) => "/problems/empty-array-element",
+            Error::EmptyArrayField { .. } => "/problems/empty-array-field",
+            Error::InternalError(_) => "/problems/internal-error",
+            Error::JsonRejection(JsonRejection::MissingJsonContentType(_)) => {
+                "/problems/unsupported-media-type"
+            }
+            Error::JsonRejection(_) => "/problems/invalid-json",
+            Error::QueryRejection(_) => "/problems/invalid-query",
+        }
+    }
+
+    // A stable machine readable code for every kind of error, clients can match on it instead of
+    // the message which may change.
+    fn code(&self) -> &'static str {
+        match self {
+            Error::NotFound => "NotFound",
+            Error::Unauthorized => "Unauthorized",
+            Error::Forbidden => "Forbidden",
+            Error::PreconditionFailed => "PreconditionFailed",
+            Error::TooManyCharacters { .. } => "TooManyCharacters",
+            Error::OutOfRange { .. } => "OutOfRange",
+            Error::CoordinateOutOfRange { .. } => "CoordinateOutOfRange",
+            Error::IncompleteCoordinates => "IncompleteCoordinates",
+            Error::InvalidColor(_) => "InvalidColor",
+            Error::ColorNotInPalette(_) => "ColorNotInPalette",
+            Error::UnknownColorGroup(_) => "UnknownColorGroup",
+            Error::DurationTooShort { .. } => "DurationTooShort",
+            Error::TooFarInFuture { .. } => "TooFarInFuture",
+            Error::BlockedWord(_) => "BlockedWord",
+            Error::InvalidTimezone(_) => "InvalidTimezone",
+            Error::InvalidRecurrence(_) => "InvalidRecurrence",
+            Error::InvalidDate(_) => "InvalidDate",
+            Error::InvalidCalendar => "InvalidCalendar",
+            Error::UnsupportedMediaType(_) => "UnsupportedMediaType",
+            Error::InvalidWindow => "InvalidWindow",
+            Error::WindowTooLong { .. } => "WindowTooLong",
+            Error::InvalidDateRange => "InvalidDateRange",
+            Error::UserExists => "UserExists",
+            Error::UnknownUser(_) => "UnknownUser",
+            Error::DependencyCycle => "DependencyCycle",
+            Error::DependencyViolation { .. } => "DependencyViolation",
+            Error::OwnerConflict(_) => "OwnerConflict",
+            Error::MissingField(_) => "MissingField",
+            Error::EmptyTitle => "EmptyTitle",
+            Error::EmptyField(_) => "EmptyField",
+            Error::EmptyArrayElement(_) => "EmptyArrayElement",
+            Error::EmptyArrayField { .. } => "EmptyArrayField",
+            Error::InternalError(_) => "InternalError",
+            Error::JsonRejection(JsonRejection::MissingJsonContentType(_)) => {
+                "UnsupportedMediaType"
+            }
+            Error::JsonRejection(_) => "InvalidJson",
+            Error::QueryRejection(_) => "InvalidQuery",
+        }
+    }
 }
 
 // This is where we define what axum (web framework) should actually do with the error.
@@ -62,7 +277,16 @@ impl IntoResponse for Error {
         // First we figure out which status code the error correlates to.
         let status = match &self {
             Error::Unauthorized => StatusCode::UNAUTHORIZED,
+            Error::Forbidden => StatusCode::FORBIDDEN,
             Error::NotFound => StatusCode::NOT_FOUND,
+            Error::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Error::DependencyCycle
+            | Error::DependencyViolation { .. }
+            | Error::OwnerConflict(_) => StatusCode::CONFLICT,
+            // axum already tells us when the body was not sent as JSON, this has to come before
+            // the other JSON rejections which are bad requests.
+            Error::JsonRejection(JsonRejection::MissingJsonContentType(_))
+            | Error::UnsupportedMediaType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
             Error::InternalError(e) => {
                 // In the case of an internal error we won't return any information to the front
                 // end so we log it instead so that we don't lose that information.
@@ -77,13 +301,40 @@ impl IntoResponse for Error {
                 StatusCode::INTERNAL_SERVER_ERROR
             }
             Error::TooManyCharacters { .. }
+            | Error::OutOfRange { .. }
+            | Error::CoordinateOutOfRange { .. }
+            | Error::IncompleteCoordinates
+            | Error::InvalidColor(_)
+            | Error::ColorNotInPalette(_)
+            | Error::UnknownColorGroup(_)
+            | Error::InvalidWindow
+            | Error::WindowTooLong { .. }
+            | Error::InvalidDateRange
+            | Error::InvalidTimezone(_)
+            | Error::InvalidDate(_)
+            | Error::InvalidRecurrence(_)
+            | Error::InvalidCalendar
+            | Error::BlockedWord(_)
+            | Error::DurationTooShort { .. }
+            | Error::TooFarInFuture { .. }
             | Error::UserExists
+            | Error::UnknownUser(_)
             | Error::JsonRejection(_)
+            | Error::QueryRejection(_)
             | Error::EmptyField(_)
+            | Error::MissingField(_)
+            | Error::EmptyTitle
             | Error::EmptyArrayElement(_)
             | Error::EmptyArrayField { .. } => StatusCode::BAD_REQUEST,
         };
 
+        let problem_type = self.problem_type();
+        let code = self.code();
+        let conflicts = match &self {
+            Error::OwnerConflict(ids) => Some(ids.clone()),
+            _ => None,
+        };
+
         // At some point I noticed that the errors were quite bad when invalid JSON was sent in so
         // I made sure to unwrap the actual error from the useless wrappers around it and return
         // that instead. This could possibly be improved but it's already more helpful to the API
@@ -101,12 +352,35 @@ impl IntoResponse for Error {
             self.to_string()
         };
 
-        // We contruct the actual body of the response.
-        let body = Json(json!({
+        // We contruct the actual body of the response. `message` is not part of the problem
+        // details but was the only field before them so we keep it for existing clients, `error`
+        // is an extension member holding the code of the error.
+        let mut body = json!({
+            "type": problem_type,
+            "error": code,
+            "title": status.canonical_reason().unwrap_or_default(),
+            "status": status.as_u16(),
+            "detail": message,
             "message": message,
-        }));
+        });
+
+        // Clients shouldn't have to parse the message to find out which events are in the way.
+        if let Some(conflicts) = conflicts {
+            body["conflicts"] = json!(conflicts);
+        }
+
+        // Errors created outside of a request, like in tests, have no instance to point to.
+        if let Ok(path) = REQUEST_PATH.try_with(|path| path.clone()) {
+            body["instance"] = json!(path);
+        }
 
-        // The combination of status code and body is our response.
-        (status, body).into_response()
+        // The combination of status code, content type and body is our response. The header
+        // replaces the `application/json` content type set by `Json`.
+        (
+            status,
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            Json(body),
+        )
+            .into_response()
     }
 }
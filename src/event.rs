@@ -1,16 +1,33 @@
-use crate::util::unix_timestamp;
-use anyhow::Context;
-use axum::extract::rejection::JsonRejection;
-use axum::extract::Path;
+use crate::util::{
+    check_bounded_window, check_hex_color, check_length, check_palette, check_window, comma_string,
+    day_bounds, filter_words, iso_timestamp, local_datetime, parse_date, parse_timezone,
+    request_timezone, round_coordinate, unix_timestamp, validate_coordinates,
+};
+use anyhow::{anyhow, Context};
+use axum::body::StreamBody;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{Path, Query};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
 use axum::{Extension, Json};
+use diesel::debug_query;
 use diesel::prelude::*;
+use diesel::sqlite::Sqlite;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{cmp::Reverse, collections::HashMap, sync::Arc};
+use time::Weekday;
+use time_tz::Tz;
 use tracing::debug;
 use ts_rs::TS;
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::error::Error;
-use crate::schema::events;
+use crate::config::{Config, WorkingHours};
+use crate::dependency::{self, DependencyCheckQuery};
+use crate::error::{get_conn, Error};
+use crate::ics::{self, IcsEvent, IcsEventReport};
+use crate::recurrence::{Recurrence, MAX_OCCURRENCES};
+use crate::schema::{events, users};
 use crate::SqlitePool;
 
 // TODO The event struct needs to be enriched by user data. This will
@@ -19,7 +36,7 @@ use crate::SqlitePool;
 // `organizer` - the authenticated user ID who created the event (string / i64)
 // `guests`    - list of available authenticated users (?) who are invited to the users (Vec<string / i64>)
 
-#[derive(Debug, Serialize, TS, ToSchema, Queryable, Insertable)]
+#[derive(Debug, Clone, Serialize, TS, ToSchema, Queryable, Insertable)]
 #[ts(export, export_to = "dist/")]
 pub struct Event {
     #[schema(example = 1)]
@@ -41,10 +58,10 @@ pub struct Event {
     pub end_date: i64,
 
     #[schema(example = 60.0520)]
-    pub location_lng: Option<f32>,
+    pub location_lng: Option<f64>,
 
     #[schema(example = 7.4142)]
-    pub location_lat: Option<f32>,
+    pub location_lat: Option<f64>,
 
     #[schema(example = "Hardangervidda")]
     pub location_name: Option<String>,
@@ -54,6 +71,480 @@ pub struct Event {
 
     #[schema(example = 1691830600)]
     pub edited_at: Option<i64>,
+
+    #[schema(example = 0)]
+    pub priority: i64,
+
+    #[schema(example = 1)]
+    pub version: i64,
+
+    /// Drafts are only listed when explicitly requested.
+    #[schema(example = false)]
+    pub draft: bool,
+
+    /// An RFC 5545 RRULE, the event repeats according to it starting with this occurrence.
+    #[schema(example = "FREQ=WEEKLY;COUNT=52")]
+    pub recurrence: Option<String>,
+
+    /// Username of the user who created the event.
+    #[schema(example = "alice")]
+    pub owner: Option<String>,
+
+    /// When the event was deleted, deleted events are only returned when explicitly requested.
+    #[schema(example = 1691830900)]
+    pub deleted_at: Option<i64>,
+
+    /// The event lasts whole days, its times have no meaning and exports only use the dates.
+    #[schema(example = false)]
+    pub all_day: bool,
+
+    /// IANA timezone the event was planned in, for showing its times the way they were meant.
+    #[schema(example = "Europe/Oslo")]
+    pub timezone: Option<String>,
+}
+
+/// An event in the shape expected by the FullCalendar front end library.
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct FullCalendarEvent {
+    #[schema(example = 1)]
+    pub id: i64,
+
+    #[schema(example = "Big Mike")]
+    pub title: String,
+
+    #[schema(example = "2023-08-05T09:00:00Z")]
+    pub start: String,
+
+    #[schema(example = "2023-08-12T09:00:00Z")]
+    pub end: String,
+
+    #[schema(example = "#87d45d")]
+    pub color: String,
+
+    #[schema(example = false)]
+    pub all_day: bool,
+}
+
+impl FullCalendarEvent {
+    fn from_event(event: Event) -> anyhow::Result<Self> {
+        Ok(FullCalendarEvent {
+            id: event.id,
+            title: event.title,
+            start: iso_timestamp(event.start_date)?,
+            end: iso_timestamp(event.end_date)?,
+            color: event.color,
+            all_day: event.all_day,
+        })
+    }
+}
+
+/// The orders events can be returned in by `get_all`, a leading `-` means descending.
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EventSort {
+    #[default]
+    #[serde(rename = "start_date")]
+    StartDate,
+
+    #[serde(rename = "-start_date")]
+    StartDateDesc,
+
+    #[serde(rename = "created_at")]
+    CreatedAt,
+
+    #[serde(rename = "-created_at")]
+    CreatedAtDesc,
+
+    /// Events which were never edited are ordered by when they were created.
+    #[serde(rename = "edited_at")]
+    EditedAt,
+
+    #[serde(rename = "-edited_at")]
+    EditedAtDesc,
+
+    /// Highest priority first, events with the same priority are ordered by their start date.
+    Priority,
+
+    /// Events starting closest to now first, no matter if they are in the past or the future.
+    Soonest,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EventWeekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl From<EventWeekday> for Weekday {
+    fn from(weekday: EventWeekday) -> Self {
+        match weekday {
+            EventWeekday::Monday => Weekday::Monday,
+            EventWeekday::Tuesday => Weekday::Tuesday,
+            EventWeekday::Wednesday => Weekday::Wednesday,
+            EventWeekday::Thursday => Weekday::Thursday,
+            EventWeekday::Friday => Weekday::Friday,
+            EventWeekday::Saturday => Weekday::Saturday,
+            EventWeekday::Sunday => Weekday::Sunday,
+        }
+    }
+}
+
+/// The shapes events can be returned in by `get_all`.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EventFormat {
+    Fullcalendar,
+}
+
+// Serialize is only used to show the parsed query when explaining it, ToSchema for the body of
+// `post_export`.
+#[derive(Debug, Serialize, Deserialize, IntoParams, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct EventQuery {
+    /// Return the events in an alternative shape instead of `Event`.
+    #[param(example = "fullcalendar")]
+    pub format: Option<EventFormat>,
+
+    /// Only return events created within this many seconds from now.
+    #[param(example = 86400)]
+    pub created_within_seconds: Option<u32>,
+
+    /// Only return events edited at or after this unix timestamp, events which were never edited
+    /// count as edited when they were created.
+    #[param(example = 1690848000)]
+    pub since: Option<i64>,
+
+    /// Only return events lasting at least this many seconds.
+    #[param(example = 3600)]
+    pub min_duration_seconds: Option<i64>,
+
+    /// Only return events lasting at most this many seconds.
+    #[param(example = 604800)]
+    pub max_duration_seconds: Option<i64>,
+
+    /// The order to return the events in, defaults to `start_date`. Unknown orders are rejected.
+    #[param(example = "-start_date")]
+    pub sort: Option<EventSort>,
+
+    /// Only return events starting on this day of the week.
+    #[param(example = "monday")]
+    pub weekday: Option<EventWeekday>,
+
+    /// IANA timezone used to determine the day of the week, defaults to DEFAULT_TIMEZONE or UTC.
+    #[param(example = "Europe/Oslo")]
+    pub tz: Option<String>,
+
+    /// Only return events which end after this unix timestamp.
+    #[param(example = 1690848000)]
+    pub from: Option<i64>,
+
+    /// Only return events which start before this unix timestamp.
+    #[param(example = 1693526400)]
+    pub to: Option<i64>,
+
+    /// Only return events whose title contains this text, ignoring case.
+    #[param(example = "hike")]
+    pub q: Option<String>,
+
+    /// Also return events whose description contains `q`.
+    #[param(example = true)]
+    pub search_description: Option<bool>,
+
+    /// Only return events with one of the colors of this configured color group.
+    #[param(example = "work")]
+    pub color_group: Option<String>,
+
+    /// Also return events which are still drafts.
+    #[param(example = true)]
+    pub include_drafts: Option<bool>,
+
+    /// Only return events owned by one of these comma separated users, ignoring case. Unknown
+    /// users simply don't own any events.
+    #[param(example = "alice,bob", value_type = Option<String>)]
+    #[schema(example = "alice,bob", value_type = Option<String>)]
+    #[serde(default, deserialize_with = "comma_string")]
+    pub owners: Option<Vec<String>>,
+
+    /// Only return the ids of the matching events instead of the events, takes precedence over
+    /// `format`.
+    #[param(example = true)]
+    pub ids_only: Option<bool>,
+
+    /// How many events to return at most, defaults to 50 and can't be more than 500.
+    #[param(example = 50)]
+    pub limit: Option<u32>,
+
+    /// How many events to skip before returning any.
+    #[param(example = 0)]
+    pub offset: Option<u32>,
+
+    /// Instead of the events return how the query was understood and the SQL it runs.
+    #[param(example = true)]
+    pub explain: Option<bool>,
+}
+
+/// How `get_all` handles a query, returned instead of the events with `explain`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventQueryExplanation {
+    /// The query parameters as they were parsed.
+    pub filters: EventQuery,
+
+    /// The SQL which loads the events, including the values bound to it.
+    pub sql: String,
+
+    /// The steps which happen in the server after loading the events, in order.
+    pub in_memory: Vec<&'static str>,
+}
+
+diesel::sql_function!(fn lower(x: diesel::sql_types::Text) -> diesel::sql_types::Text);
+diesel::sql_function!(
+    #[sql_name = "lower"]
+    fn lower_nullable(
+        x: diesel::sql_types::Nullable<diesel::sql_types::Text>
+    ) -> diesel::sql_types::Nullable<diesel::sql_types::Text>
+);
+diesel::sql_function!(fn abs(x: diesel::sql_types::BigInt) -> diesel::sql_types::BigInt);
+diesel::sql_function!(
+    fn coalesce(
+        x: diesel::sql_types::Nullable<diesel::sql_types::BigInt>,
+        y: diesel::sql_types::BigInt,
+    ) -> diesel::sql_types::BigInt
+);
+
+pub const DEFAULT_LIMIT: u32 = 50;
+pub const MAX_LIMIT: u32 = 500;
+
+// The body returned by `get_all` depends on the requested format, `untagged` makes serde
+// serialize only the contained list.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum EventList {
+    Explain(EventQueryExplanation),
+    Ids(Vec<i64>),
+    Native(Vec<Event>),
+    FullCalendar(Vec<FullCalendarEvent>),
+}
+
+// Replaces recurring events by their occurrences within the window, in place so the order of the
+// events is kept. Every occurrence is a copy of the stored event with its dates moved.
+fn expand_recurrences(events: Vec<Event>, from: i64, to: i64) -> Vec<Event> {
+    let mut expanded = Vec::with_capacity(events.len());
+    for event in events {
+        let recurrence = event
+            .recurrence
+            .as_deref()
+            .and_then(|recurrence| recurrence.parse::<Recurrence>().ok());
+        let Some(recurrence) = recurrence else {
+            expanded.push(event);
+            continue;
+        };
+
+        let duration = event.end_date - event.start_date;
+        let starts = recurrence.occurrences(event.start_date, duration, from, to, MAX_OCCURRENCES);
+        for start in starts {
+            expanded.push(Event {
+                start_date: start,
+                end_date: start + duration,
+                ..event.clone()
+            });
+        }
+    }
+
+    expanded
+}
+
+// Checks the parts of an event query which can be invalid, returning the timezone and the colors of
+// the color group it refers to.
+fn check_event_query<'a>(
+    config: &'a Config,
+    query: &EventQuery,
+) -> Result<(Option<&'static Tz>, Option<&'a Vec<String>>), Error> {
+    let tz = request_timezone(config, query.tz.as_deref())?;
+    let group_colors = match &query.color_group {
+        Some(name) => Some(
+            config
+                .color_groups
+                .get(name)
+                .ok_or_else(|| Error::UnknownColorGroup(name.clone()))?,
+        ),
+        None => None,
+    };
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        check_bounded_window(config, from, to)?;
+    }
+
+    Ok((tz, group_colors))
+}
+
+// The character used to escape the wildcards of LIKE patterns.
+const LIKE_ESCAPE: char = '\\';
+
+// Turns text into a LIKE pattern matching any text containing it, `%` and `_` in it match only
+// themselves.
+fn contains_pattern(text: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in text.chars() {
+        if matches!(c, '%' | '_' | LIKE_ESCAPE) {
+            pattern.push(LIKE_ESCAPE);
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+// Applies the filters of `get_all` which sqlite can handle, the result is used both for loading the
+// events and for counting them.
+fn filter_events<'a>(
+    query: &'a EventQuery,
+    group_colors: Option<&'a Vec<String>>,
+) -> events::BoxedQuery<'a, Sqlite> {
+    // Boxing the query lets us conditionally add filters depending on the query parameters.
+    let mut events_query = events::dsl::events
+        .filter(events::dsl::deleted_at.is_null())
+        .into_boxed();
+    if !query.include_drafts.unwrap_or(false) {
+        events_query = events_query.filter(events::dsl::draft.eq(false));
+    }
+
+    if let Some(seconds) = query.created_within_seconds {
+        let created_after = unix_timestamp() - i64::from(seconds);
+        events_query = events_query.filter(events::dsl::created_at.ge(created_after));
+    }
+
+    if let Some(since) = query.since {
+        events_query = events_query.filter(last_change().ge(since));
+    }
+
+    // Events overlapping the window are returned, including ones which started before it or end
+    // after it. When both ends of the window are given recurring events are expanded into their
+    // occurrences, these may overlap the window even if the first occurrence doesn't.
+    let window = query.from.zip(query.to);
+    if let Some(from) = query.from {
+        events_query = if window.is_some() {
+            events_query.filter(
+                events::dsl::end_date
+                    .gt(from)
+                    .or(events::dsl::recurrence.is_not_null()),
+            )
+        } else {
+            events_query.filter(events::dsl::end_date.gt(from))
+        };
+    }
+    if let Some(to) = query.to {
+        events_query = events_query.filter(events::dsl::start_date.lt(to));
+    }
+
+    // LIKE ignores the case of ASCII letters in sqlite.
+    if let Some(q) = &query.q {
+        let pattern = contains_pattern(q);
+        events_query = if query.search_description.unwrap_or(false) {
+            events_query.filter(
+                events::dsl::title
+                    .like(pattern.clone())
+                    .escape(LIKE_ESCAPE)
+                    .or(events::dsl::description.like(pattern).escape(LIKE_ESCAPE)),
+            )
+        } else {
+            events_query.filter(events::dsl::title.like(pattern).escape(LIKE_ESCAPE))
+        };
+    }
+
+    // Group colors are normalized, the stored colors may not be.
+    if let Some(colors) = group_colors {
+        events_query = events_query.filter(lower(events::dsl::color).eq_any(colors));
+    }
+
+    if let Some(owners) = &query.owners {
+        let owners: Vec<String> = owners.iter().map(|owner| owner.to_lowercase()).collect();
+        events_query = events_query.filter(lower_nullable(events::dsl::owner).eq_any(owners));
+    }
+
+    let duration = events::dsl::end_date - events::dsl::start_date;
+    if let Some(min) = query.min_duration_seconds {
+        events_query = events_query.filter(duration.ge(min));
+    }
+    if let Some(max) = query.max_duration_seconds {
+        events_query = events_query.filter(duration.le(max));
+    }
+
+    events_query
+}
+
+// When an event was last changed, events which were never edited were last changed when they were
+// created.
+fn last_change() -> coalesce::HelperType<events::edited_at, events::created_at> {
+    coalesce(events::dsl::edited_at, events::dsl::created_at)
+}
+
+fn sort_events(
+    events_query: events::BoxedQuery<'_, Sqlite>,
+    sort: EventSort,
+    now: i64,
+) -> events::BoxedQuery<'_, Sqlite> {
+    match sort {
+        EventSort::StartDate => events_query.order(events::dsl::start_date.asc()),
+        EventSort::StartDateDesc => events_query.order(events::dsl::start_date.desc()),
+        EventSort::CreatedAt => events_query.order(events::dsl::created_at.asc()),
+        EventSort::CreatedAtDesc => events_query.order(events::dsl::created_at.desc()),
+        EventSort::EditedAt => events_query.order(last_change().asc()),
+        EventSort::EditedAtDesc => events_query.order(last_change().desc()),
+        EventSort::Priority => {
+            events_query.order((events::dsl::priority.desc(), events::dsl::start_date.asc()))
+        }
+        EventSort::Soonest => events_query.order((
+            abs(events::dsl::start_date - now).asc(),
+            events::dsl::id.asc(),
+        )),
+    }
+}
+
+// The day of the week depends on the timezone which sqlite doesn't know about so this filter is
+// applied after loading the events, the same goes for expanding recurring events.
+fn filter_loaded_events(
+    mut events: Vec<Event>,
+    query: &EventQuery,
+    tz: Option<&Tz>,
+    now: i64,
+) -> Vec<Event> {
+    if let Some((from, to)) = query.from.zip(query.to) {
+        events = expand_recurrences(events, from, to);
+
+        // Occurrences are placed where their recurring event was, which is only the right spot
+        // for them when ordering by something other than their dates. The sorts are stable so
+        // events which compare equal keep the order sqlite returned them in.
+        match query.sort.unwrap_or_default() {
+            EventSort::StartDate => events.sort_by_key(|event| event.start_date),
+            EventSort::StartDateDesc => events.sort_by_key(|event| Reverse(event.start_date)),
+            EventSort::Soonest => {
+                events.sort_by_key(|event| ((event.start_date - now).abs(), event.id))
+            }
+            EventSort::CreatedAt
+            | EventSort::CreatedAtDesc
+            | EventSort::EditedAt
+            | EventSort::EditedAtDesc
+            | EventSort::Priority => {}
+        }
+    }
+
+    if let Some(weekday) = query.weekday {
+        let weekday = Weekday::from(weekday);
+        events.retain(|event| {
+            local_datetime(event.start_date, tz).map(|date| date.weekday()) == Some(weekday)
+        });
+    }
+
+    events
 }
 
 /// Get a list of all events
@@ -61,19 +552,251 @@ pub struct Event {
     get,
     path = "/api/event",
     responses(
-        (status = 200, description = "Events are returned", body = [Event]),
-    )
+        (status = 200, description = "Events are returned, or only their ids with idsOnly, or an explanation of the query with explain", body = [Event],
+            headers(
+                ("X-Result-Truncated" = String, description = "Set to true when more events matched than the configured soft limit, only that many are returned"),
+                ("X-Total-Count" = i64, description = "How many events matched, only set along with X-Result-Truncated"),
+            )
+        ),
+    ),
+    params(EventQuery)
 )]
 // Return all events
-pub async fn get_all(Extension(pool): Extension<SqlitePool>) -> Result<Json<Vec<Event>>, Error> {
-    let mut conn = pool.get().await.expect("can connect to sqlite");
-    debug!("Loading all events");
-    let events = events::dsl::events
-        .load(&mut *conn)
-        .context("Failed to load events")?;
+pub async fn get_all(
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<EventQuery>, QueryRejection>,
+) -> Result<(HeaderMap, Json<EventList>), Error> {
+    let Query(query) = query?;
+    let (tz, group_colors) = check_event_query(&config, &query)?;
+
+    let mut conn = get_conn(&pool).await?;
+    debug!(?query, "Loading all events");
+
+    let now = unix_timestamp();
+    let mut events_query = sort_events(
+        filter_events(&query, group_colors),
+        query.sort.unwrap_or_default(),
+        now,
+    );
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let offset = query.offset.unwrap_or(0);
+    let window = query.from.zip(query.to);
+
+    // Some filters are applied after loading the events, see `filter_loaded_events`, in which
+    // case the paging has to happen after them as well.
+    let in_memory = query.weekday.is_some() || window.is_some();
+    if !in_memory {
+        events_query = events_query
+            .limit(i64::from(limit))
+            .offset(i64::from(offset));
+    }
+
+    if query.explain.unwrap_or(false) {
+        let sql = debug_query::<Sqlite, _>(&events_query).to_string();
+        // The query borrows from the parameters we want to return.
+        drop(events_query);
+
+        let mut steps = Vec::new();
+        if window.is_some() {
+            steps.push("expand recurring events into their occurrences within the window");
+        }
+        if query.weekday.is_some() {
+            steps.push("keep the events starting on the weekday in the timezone");
+        }
+        if in_memory {
+            steps.push("skip offset events and keep at most limit events");
+        }
+        if config.result_soft_limit.is_some() {
+            steps.push("keep at most the configured soft limit of events");
+        }
+
+        let explanation = EventQueryExplanation {
+            filters: query,
+            sql,
+            in_memory: steps,
+        };
+        return Ok((HeaderMap::new(), Json(EventList::Explain(explanation))));
+    }
+
+    let (mut events, total): (Vec<Event>, Option<usize>) = if in_memory {
+        let events: Vec<Event> = events_query
+            .load(&mut *conn)
+            .context("Failed to load events")?;
+        let events = filter_loaded_events(events, &query, tz, now);
+
+        let total = events.len();
+        let events = events
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect();
+        (events, Some(total))
+    } else {
+        let events = events_query
+            .load(&mut *conn)
+            .context("Failed to load events")?;
+        (events, None)
+    };
+
+    // Rather than failing requests which match more events than the configured soft limit we
+    // return at most that many and tell the client how many there are so it can narrow down its
+    // query. The loaded events are already paged, so the limit is compared to all the matching
+    // events instead.
+    let mut headers = HeaderMap::new();
+    if let Some(soft_limit) = config.result_soft_limit {
+        let total = match total {
+            Some(total) => total as i64,
+            None => filter_events(&query, group_colors)
+                .count()
+                .get_result::<i64>(&mut *conn)
+                .context("Failed to count events")?,
+        };
+
+        if total > i64::from(soft_limit) {
+            events.truncate(soft_limit as usize);
+            debug!(total, soft_limit, "Truncated events");
+            headers.insert("x-result-truncated", HeaderValue::from_static("true"));
+            headers.insert("x-total-count", HeaderValue::from(total));
+        }
+    }
 
     debug!(count = events.len(), "Returning events");
-    Ok(Json(events))
+    if query.ids_only.unwrap_or(false) {
+        let ids = events.into_iter().map(|event| event.id).collect();
+        return Ok((headers, Json(EventList::Ids(ids))));
+    }
+
+    let events = match query.format {
+        None => EventList::Native(events),
+        Some(EventFormat::Fullcalendar) => EventList::FullCalendar(
+            events
+                .into_iter()
+                .map(FullCalendarEvent::from_event)
+                .collect::<anyhow::Result<_>>()?,
+        ),
+    };
+
+    Ok((headers, Json(events)))
+}
+
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct EventCount {
+    #[schema(example = 23)]
+    pub count: i64,
+}
+
+/// Count the events matching a query
+///
+/// Takes the same filters as `GET /api/event`, `limit`, `offset`, `format`, `idsOnly` and `explain`
+/// are ignored.
+#[utoipa::path(
+    get,
+    path = "/api/event/count",
+    responses(
+        (status = 200, description = "The number of matching events", body = EventCount),
+        (status = 400, description = "The query is invalid"),
+    ),
+    params(EventQuery)
+)]
+pub async fn count(
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<EventQuery>, QueryRejection>,
+) -> Result<Json<EventCount>, Error> {
+    let Query(query) = query?;
+    let (tz, group_colors) = check_event_query(&config, &query)?;
+
+    let mut conn = get_conn(&pool).await?;
+    debug!(?query, "Counting events");
+
+    // Only queries with filters applied after loading have to load the events, the rest are
+    // counted by sqlite.
+    let count = if query.weekday.is_some() || query.from.zip(query.to).is_some() {
+        let events: Vec<Event> = filter_events(&query, group_colors)
+            .load(&mut *conn)
+            .context("Failed to load events")?;
+        filter_loaded_events(events, &query, tz, unix_timestamp()).len() as i64
+    } else {
+        filter_events(&query, group_colors)
+            .count()
+            .get_result(&mut *conn)
+            .context("Failed to count events")?
+    };
+
+    Ok(Json(EventCount { count }))
+}
+
+/// The shapes a single event can be returned in by `get_by_id`.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EventDetailFormat {
+    /// A schema.org `Event` as JSON-LD, meant to be embedded in pages as structured data.
+    Jsonld,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct EventDetailQuery {
+    /// Return the event in an alternative shape instead of `Event`.
+    #[param(example = "jsonld")]
+    pub format: Option<EventDetailFormat>,
+
+    /// Also return the event if it is still a draft, drafts are not found otherwise.
+    #[param(example = true)]
+    pub include_drafts: Option<bool>,
+
+    /// Also return the event if it was deleted, for example to preview restoring it. Deleted
+    /// events are not found otherwise.
+    #[param(example = true)]
+    pub include_deleted: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum EventDetail {
+    Native(Event),
+    JsonLd(serde_json::Value),
+}
+
+// Converts an event into a schema.org `Event`, fields we don't have any data for are left out.
+// https://schema.org/Event
+fn jsonld_event(event: Event) -> anyhow::Result<serde_json::Value> {
+    let mut jsonld = json!({
+        "@context": "https://schema.org",
+        "@type": "Event",
+        "name": event.title,
+        "startDate": iso_timestamp(event.start_date)?,
+        "endDate": iso_timestamp(event.end_date)?,
+    });
+
+    if let Some(description) = event.description {
+        jsonld["description"] = json!(description);
+    }
+
+    let geo = match (event.location_lat, event.location_lng) {
+        (Some(latitude), Some(longitude)) => Some(json!({
+            "@type": "GeoCoordinates",
+            "latitude": latitude,
+            "longitude": longitude,
+        })),
+        _ => None,
+    };
+    if event.location_name.is_some() || geo.is_some() {
+        let mut location = json!({ "@type": "Place" });
+        if let Some(name) = event.location_name {
+            location["name"] = json!(name);
+        }
+        if let Some(geo) = geo {
+            location["geo"] = geo;
+        }
+        jsonld["location"] = location;
+    }
+
+    Ok(jsonld)
 }
 
 /// Get an event by its id
@@ -81,11 +804,14 @@ pub async fn get_all(Extension(pool): Extension<SqlitePool>) -> Result<Json<Vec<
     get,
     path = "/api/event/{id}",
     responses(
-        (status = 200, description = "Event data is returned", body = Event),
+        (status = 200, description = "Event data is returned", body = Event,
+            headers(("ETag" = String, description = "The version of the event in quotes, with `-jsonld` appended for the JSON-LD format"))),
+        (status = 304, description = "The event did not change since the ETag in If-None-Match"),
         (status = 404, description = "Event does not exist"),
     ),
     params(
         ("id" = i64, Path, description = "Identifier of the event"),
+        EventDetailQuery,
     )
 )]
 
@@ -93,8 +819,11 @@ pub async fn get_all(Extension(pool): Extension<SqlitePool>) -> Result<Json<Vec<
 pub async fn get_by_id(
     Path(id): Path<i64>,
     Extension(pool): Extension<SqlitePool>,
-) -> Result<Json<Event>, Error> {
-    let mut conn = pool.get().await.expect("can connect to sqlite");
+    query: Result<Query<EventDetailQuery>, QueryRejection>,
+    headers: HeaderMap,
+) -> Result<Response, Error> {
+    let Query(query) = query?;
+    let mut conn = get_conn(&pool).await?;
     debug!(id, "Loading event with id");
 
     let event = events::dsl::events
@@ -102,10 +831,62 @@ pub async fn get_by_id(
         .first::<Event>(&mut *conn)
         .optional()
         .context("Failed to query event")?
+        .filter(|event| !event.draft || query.include_drafts.unwrap_or(false))
+        .filter(|event| event.deleted_at.is_none() || query.include_deleted.unwrap_or(false))
         .ok_or(Error::NotFound)?;
 
     debug!(?event, "Found Event");
-    Ok(Json(event))
+    let etag = event_etag(&event, query.format.as_ref())?;
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+
+    let event = match query.format {
+        None => EventDetail::Native(event),
+        Some(EventDetailFormat::Jsonld) => EventDetail::JsonLd(jsonld_event(event)?),
+    };
+
+    Ok(([(header::ETAG, etag)], Json(event)).into_response())
+}
+
+/// Export an event as an iCalendar file
+#[utoipa::path(
+    get,
+    path = "/api/event/{id}/ics",
+    responses(
+        (status = 200, description = "The event as a VCALENDAR", body = String, content_type = "text/calendar"),
+        (status = 404, description = "Event does not exist"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event"),
+    )
+)]
+pub async fn get_ics(
+    Path(id): Path<i64>,
+    Extension(pool): Extension<SqlitePool>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = get_conn(&pool).await?;
+    let event = events::dsl::events
+        .filter(events::dsl::id.eq(id))
+        .filter(events::dsl::deleted_at.is_null())
+        .filter(events::dsl::draft.eq(false))
+        .first::<Event>(&mut *conn)
+        .optional()
+        .context("Failed to query event")?
+        .ok_or(Error::NotFound)?;
+
+    let calendar = ics::write_calendar([&event])?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, ics::CONTENT_TYPE.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"event-{id}.ics\""),
+            ),
+        ],
+        calendar,
+    ))
 }
 
 // Post Event
@@ -129,34 +910,182 @@ pub struct PostEvent {
     pub end_date: i64,
 
     #[schema(example = 60.0520)]
-    pub location_lng: Option<f32>,
+    pub location_lng: Option<f64>,
 
     #[schema(example = 7.4142)]
-    pub location_lat: Option<f32>,
+    pub location_lat: Option<f64>,
 
     #[schema(example = "Hardangervidda")]
     pub location_name: Option<String>,
 
+    /// Create the event as a draft which has to be published before it is listed.
+    #[schema(example = false)]
+    #[serde(default)]
+    pub draft: bool,
+
+    /// An RFC 5545 RRULE, only FREQ, INTERVAL, COUNT (up to 10000) and UNTIL are
+    /// supported.
+    #[schema(example = "FREQ=WEEKLY;COUNT=52")]
+    pub recurrence: Option<String>,
+
+    /// Username of the user creating the event, the user has to exist.
+    #[schema(example = "alice")]
+    pub owner: Option<String>,
+
+    /// The event lasts whole days, its times have no meaning.
+    #[schema(example = false)]
+    #[serde(default)]
+    pub all_day: bool,
+
+    /// IANA timezone the event is planned in, it has to be a known one.
+    #[schema(example = "Europe/Oslo")]
+    pub timezone: Option<String>,
+
     #[ts(skip)]
     #[serde(skip, default = "unix_timestamp")]
     pub created_at: i64,
 }
 
+// Checks the invariants spanning multiple fields of an event, this is used on the final state of
+// an event right before it is written.
+fn validate_event(config: &Config, event: &Event) -> Result<(), Error> {
+    check_date_range(event.start_date, event.end_date)?;
+    check_duration(config, event.start_date, event.end_date)?;
+    check_horizon(config, event.start_date)?;
+    check_recurrence(event.recurrence.as_deref())?;
+    check_timezone(event.timezone.as_deref())?;
+    validate_coordinates(event.location_lat, event.location_lng)
+}
+
+pub const MAX_TITLE_LENGTH: u64 = 200;
+pub const MAX_DESCRIPTION_LENGTH: u64 = 4000;
+pub const MAX_LOCATION_NAME_LENGTH: u64 = 200;
+
+// Keeps oversized text out of the database. A title made of whitespace only would show up as an
+// event without a name.
+fn check_text_fields(
+    title: Option<&str>,
+    description: Option<&str>,
+    location_name: Option<&str>,
+) -> Result<(), Error> {
+    if title.is_some_and(|title| title.trim().is_empty()) {
+        return Err(Error::EmptyTitle);
+    }
+
+    check_length("title", title, MAX_TITLE_LENGTH)?;
+    check_length("description", description, MAX_DESCRIPTION_LENGTH)?;
+    check_length("location_name", location_name, MAX_LOCATION_NAME_LENGTH)
+}
+
+// An event may not end before it starts, calendars can't render a negative span.
+fn check_date_range(start_date: i64, end_date: i64) -> Result<(), Error> {
+    if end_date < start_date {
+        return Err(Error::InvalidDateRange);
+    }
+
+    Ok(())
+}
+
+fn check_recurrence(recurrence: Option<&str>) -> Result<(), Error> {
+    if let Some(recurrence) = recurrence {
+        recurrence.parse::<Recurrence>()?;
+    }
+
+    Ok(())
+}
+
+fn check_timezone(timezone: Option<&str>) -> Result<(), Error> {
+    if let Some(timezone) = timezone {
+        parse_timezone(timezone)?;
+    }
+
+    Ok(())
+}
+
+// Events may not be scheduled further in the future than the configured horizon.
+fn check_horizon(config: &Config, start_date: i64) -> Result<(), Error> {
+    if let Some(max_future_seconds) = config.max_future_seconds {
+        let latest = unix_timestamp() + max_future_seconds;
+        if start_date > latest {
+            return Err(Error::TooFarInFuture { latest });
+        }
+    }
+
+    Ok(())
+}
+
+// Rejects events which are shorter than the configured minimum duration.
+fn check_duration(config: &Config, start_date: i64, end_date: i64) -> Result<(), Error> {
+    if let Some(minimum) = config.min_duration_seconds {
+        let duration = end_date - start_date;
+        if duration < minimum {
+            return Err(Error::DurationTooShort { duration, minimum });
+        }
+    }
+
+    Ok(())
+}
+
+// Usernames are case insensitive so we store the owner the way the user was created.
+fn owner_username(conn: &mut SqliteConnection, owner: &str) -> Result<String, Error> {
+    users::dsl::users
+        .filter(users::dsl::username.eq(owner))
+        .filter(users::dsl::deleted_at.is_null())
+        .select(users::dsl::username)
+        .first(conn)
+        .optional()
+        .context("Failed to query owner")?
+        .ok_or_else(|| Error::UnknownUser(owner.to_string()))
+}
+
 /// Post an event
 #[utoipa::path(
     post,
     path = "/api/event",
+    request_body = PostEvent,
     responses(
-        (status = 200, description = "Posted an event", body = [PostEvent]),
+        (status = 200, description = "Posted an event", body = Event),
+        (status = 400, description = "The event is invalid or its owner does not exist"),
     )
 )]
 
 pub async fn post(
     Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
     req: Result<Json<PostEvent>, JsonRejection>,
 ) -> Result<Json<Event>, Error> {
-    let Json(req) = req?;
-    let mut conn = pool.get().await.expect("can connect to sqlite");
+    let Json(mut req) = req?;
+    check_text_fields(
+        Some(&req.title),
+        req.description.as_deref(),
+        req.location_name.as_deref(),
+    )?;
+    check_date_range(req.start_date, req.end_date)?;
+    check_duration(&config, req.start_date, req.end_date)?;
+    check_horizon(&config, req.start_date)?;
+    check_recurrence(req.recurrence.as_deref())?;
+    check_timezone(req.timezone.as_deref())?;
+    filter_words("title", Some(&mut req.title), config.word_filter.as_ref())?;
+    filter_words(
+        "description",
+        req.description.as_mut(),
+        config.word_filter.as_ref(),
+    )?;
+    validate_coordinates(req.location_lat, req.location_lng)?;
+    check_hex_color(req.color.as_deref())?;
+    check_palette(req.color.as_deref(), config.color_palette.as_deref())?;
+    req.location_lng = req
+        .location_lng
+        .map(|coordinate| round_coordinate(coordinate, config.coordinate_decimals));
+    req.location_lat = req
+        .location_lat
+        .map(|coordinate| round_coordinate(coordinate, config.coordinate_decimals));
+    let mut conn = get_conn(&pool).await?;
+    req.owner = req
+        .owner
+        .as_deref()
+        .map(|owner| owner_username(&mut conn, owner))
+        .transpose()?;
 
     // Insert into db
     let event = diesel::insert_into(events::table)
@@ -169,27 +1098,125 @@ pub async fn post(
     Ok(Json(event))
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct DeleteEventQuery {
+    /// Username of the user deleting the event, required for events with an owner and refused
+    /// unless they own the event. Events without an owner can be deleted by anyone.
+    #[param(example = "alice")]
+    pub requester: Option<String>,
+}
+
 // Delete event
 #[utoipa::path(
     delete,
-    path = "/api/event",
+    path = "/api/event/{id}",
     responses(
         (status = 200, description = "Deleted an event"),
+        (status = 401, description = "The event has an owner but the requester is missing or unknown"),
+        (status = 403, description = "The requester does not own the event"),
+        (status = 404, description = "Event does not exist"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event"),
+        DeleteEventQuery,
     )
 )]
 
 pub async fn delete_by_id(
     Path(id): Path<i64>,
     Extension(pool): Extension<SqlitePool>,
+    query: Result<Query<DeleteEventQuery>, QueryRejection>,
 ) -> Result<(), Error> {
-    let mut conn = pool.get().await.expect("can connect to sqlite");
-    diesel::delete(events::dsl::events.filter(events::dsl::id.eq(id)))
-        .execute(&mut *conn)
+    let Query(query) = query?;
+    let mut conn = get_conn(&pool).await?;
+    conn.transaction::<_, Error, _>(|conn| {
+        // Claiming to be a user who doesn't exist is as good as not saying who you are.
+        if let Some(requester) = &query.requester {
+            let known: i64 = users::dsl::users
+                .filter(users::dsl::username.eq(requester))
+                .filter(users::dsl::deleted_at.is_null())
+                .count()
+                .get_result(conn)
+                .context("Failed to query requester")?;
+            if known == 0 {
+                return Err(Error::Unauthorized);
+            }
+        }
+
+        let owner: Option<String> = events::dsl::events
+            .filter(events::dsl::id.eq(id))
+            .filter(events::dsl::deleted_at.is_null())
+            .select(events::dsl::owner)
+            .first(conn)
+            .optional()
+            .context("Failed to query owner of event")?
+            .flatten();
+
+        if let Some(owner) = owner {
+            match &query.requester {
+                None => return Err(Error::Unauthorized),
+                Some(requester) if !owner.eq_ignore_ascii_case(requester) => {
+                    return Err(Error::Forbidden)
+                }
+                Some(_) => {}
+            }
+        }
+
+        // The event is only marked as deleted so it can be restored, its dependencies and
+        // attendees are kept for that as well.
+        let deleted = diesel::update(
+            events::dsl::events
+                .filter(events::dsl::id.eq(id))
+                .filter(events::dsl::deleted_at.is_null()),
+        )
+        .set(events::dsl::deleted_at.eq(unix_timestamp()))
+        .execute(conn)
         .context("Failed to delete an event")?;
 
+        if deleted == 0 {
+            return Err(Error::NotFound);
+        }
+
+        Ok(())
+    })?;
+
     Ok(())
 }
 
+/// Restore a deleted event
+#[utoipa::path(
+    post,
+    path = "/api/event/{id}/restore",
+    responses(
+        (status = 200, description = "The event was restored", body = Event),
+        (status = 404, description = "There is no deleted event with that id"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event"),
+    )
+)]
+pub async fn post_restore(
+    Path(id): Path<i64>,
+    Extension(pool): Extension<SqlitePool>,
+) -> Result<Json<Event>, Error> {
+    let mut conn = get_conn(&pool).await?;
+    let event = diesel::update(
+        events::dsl::events
+            .filter(events::dsl::id.eq(id))
+            .filter(events::dsl::deleted_at.is_not_null()),
+    )
+    .set(events::dsl::deleted_at.eq(None::<i64>))
+    .get_result(&mut *conn)
+    .optional()
+    .context("Failed to restore event")?
+    .ok_or(Error::NotFound)?;
+
+    debug!(id, "Restored event");
+    Ok(Json(event))
+}
+
 // Put Event
 #[derive(Debug, Deserialize, TS, ToSchema, AsChangeset)]
 #[ts(export, export_to = "dist/")]
@@ -211,39 +1238,1964 @@ pub struct PutEvent {
     pub end_date: Option<i64>,
 
     #[schema(example = 60.0520)]
-    pub location_lng: Option<f32>,
+    pub location_lng: Option<f64>,
 
     #[schema(example = 7.4142)]
-    pub location_lat: Option<f32>,
+    pub location_lat: Option<f64>,
 
     #[schema(example = "Hardangervidda")]
     pub location_name: Option<String>,
 
+    #[schema(example = false)]
+    pub draft: Option<bool>,
+
+    #[schema(example = "FREQ=WEEKLY;COUNT=52")]
+    pub recurrence: Option<String>,
+
+    #[schema(example = false)]
+    pub all_day: Option<bool>,
+
+    #[schema(example = "Europe/Oslo")]
+    pub timezone: Option<String>,
+
     #[ts(skip)]
     #[serde(skip, default = "unix_timestamp")]
     pub edited_at: i64,
 }
 
+impl PutEvent {
+    // A PUT to an id which does not exist yet creates the event, in that case the fields which are
+    // required when posting an event have to be present.
+    fn into_event(self, id: i64) -> Result<Event, Error> {
+        Ok(Event {
+            id,
+            title: self.title.ok_or(Error::MissingField("title"))?,
+            description: self.description,
+            color: self.color.ok_or(Error::MissingField("color"))?,
+            start_date: self.start_date.ok_or(Error::MissingField("start_date"))?,
+            end_date: self.end_date.ok_or(Error::MissingField("end_date"))?,
+            location_lng: self.location_lng,
+            location_lat: self.location_lat,
+            location_name: self.location_name,
+            created_at: unix_timestamp(),
+            edited_at: None,
+            priority: 0,
+            version: 1,
+            draft: self.draft.unwrap_or(false),
+            recurrence: self.recurrence,
+            owner: None,
+            deleted_at: None,
+            all_day: self.all_day.unwrap_or(false),
+            timezone: self.timezone,
+        })
+    }
+
+    // Applies the changes on top of the stored event, giving us the event as it would be stored
+    // after the update so we can validate it as a whole.
+    fn merged(&self, current: &Event) -> Event {
+        Event {
+            id: current.id,
+            title: self.title.clone().unwrap_or_else(|| current.title.clone()),
+            description: self
+                .description
+                .clone()
+                .or_else(|| current.description.clone()),
+            color: self.color.clone().unwrap_or_else(|| current.color.clone()),
+            start_date: self.start_date.unwrap_or(current.start_date),
+            end_date: self.end_date.unwrap_or(current.end_date),
+            location_lng: self.location_lng.or(current.location_lng),
+            location_lat: self.location_lat.or(current.location_lat),
+            location_name: self
+                .location_name
+                .clone()
+                .or_else(|| current.location_name.clone()),
+            created_at: current.created_at,
+            edited_at: Some(self.edited_at),
+            priority: current.priority,
+            version: current.version + 1,
+            draft: self.draft.unwrap_or(current.draft),
+            recurrence: self
+                .recurrence
+                .clone()
+                .or_else(|| current.recurrence.clone()),
+            owner: current.owner.clone(),
+            deleted_at: current.deleted_at,
+            all_day: self.all_day.unwrap_or(current.all_day),
+            timezone: self.timezone.clone().or_else(|| current.timezone.clone()),
+        }
+    }
+}
+
+// Foreign keys are not enforced by sqlite unless enabled on every connection, so the owner of
+// events is updated by hand when their user is renamed.
+pub fn rename_owner(conn: &mut SqliteConnection, old: &str, new: &str) -> Result<(), Error> {
+    diesel::update(events::dsl::events.filter(events::dsl::owner.eq(old)))
+        .set((
+            events::dsl::owner.eq(new),
+            events::dsl::version.eq(events::dsl::version + 1),
+        ))
+        .execute(conn)
+        .context("Failed to update owner of events")?;
+
+    Ok(())
+}
+
+// The ETag of an event is derived from nothing but its stored `version`, which every change to the
+// event increments. It therefore stays the same across requests and restarts of the server until
+// the event changes, and the ETag of the native representation is also what `put` expects in
+// If-Match. ETags identify a representation rather than the event, so the JSON-LD shape gets a
+// tag of its own by appending its format, e.g. `"4"` and `"4-jsonld"`.
+fn event_etag(event: &Event, format: Option<&EventDetailFormat>) -> anyhow::Result<HeaderValue> {
+    let etag = match format {
+        None => format!("\"{}\"", event.version),
+        Some(EventDetailFormat::Jsonld) => format!("\"{}-jsonld\"", event.version),
+    };
+    HeaderValue::from_str(&etag).context("Failed to create ETag")
+}
+
+// If-None-Match holds a list of ETags or `*`, they are compared ignoring whether they are weak.
+fn if_none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Some(value) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let Ok(etag) = etag.to_str() else {
+        return false;
+    };
+
+    value.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+// What a client expects to be updating according to the `If-Match` header.
+#[derive(Debug, PartialEq)]
+enum IfMatch {
+    // `*` matches any version as long as the event exists.
+    Any,
+    Version(i64),
+}
+
+// Reads the version a client expects to be updating from the `If-Match` header. The value may be
+// quoted like an ETag.
+fn if_match_version(headers: &HeaderMap) -> Result<Option<IfMatch>, Error> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+
+    let value = value
+        .to_str()
+        .map_err(|_| Error::PreconditionFailed)?
+        .trim();
+    if value == "*" {
+        return Ok(Some(IfMatch::Any));
+    }
+
+    let version = value
+        .trim_matches('"')
+        .parse()
+        .map_err(|_| Error::PreconditionFailed)?;
+
+    Ok(Some(IfMatch::Version(version)))
+}
+
 #[utoipa::path(
     put,
     path = "/api/event/{id}",
+    request_body = PutEvent,
     responses(
-        (status = 200, description = "Updated an event", body = [Event]),
-    )
+        (status = 200, description = "Updated an event", body = Event),
+        (status = 201, description = "No event had the id yet, so an event was created with it", body = Event),
+        (status = 404, description = "The event with the given id was deleted, the id is not reused"),
+        (status = 409, description = "The event would start before an event it depends on ends"),
+        (status = 412, description = "The event version does not match If-Match"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event"),
+        DependencyCheckQuery,
+        ("If-Match" = Option<String>, Header, description = "Only update the event if it still has this version, or with `*` only if it exists"),
+    )
 )]
-
 pub async fn put(
     Path(id): Path<i64>,
     Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<DependencyCheckQuery>, QueryRejection>,
+    headers: HeaderMap,
     req: Result<Json<PutEvent>, JsonRejection>,
+) -> Result<(StatusCode, Json<Event>), Error> {
+    let Query(query) = query?;
+    let Json(mut req) = req?;
+    check_text_fields(
+        req.title.as_deref(),
+        req.description.as_deref(),
+        req.location_name.as_deref(),
+    )?;
+    filter_words("title", req.title.as_mut(), config.word_filter.as_ref())?;
+    filter_words(
+        "description",
+        req.description.as_mut(),
+        config.word_filter.as_ref(),
+    )?;
+    check_hex_color(req.color.as_deref())?;
+    check_palette(req.color.as_deref(), config.color_palette.as_deref())?;
+    req.location_lng = req
+        .location_lng
+        .map(|coordinate| round_coordinate(coordinate, config.coordinate_decimals));
+    req.location_lat = req
+        .location_lat
+        .map(|coordinate| round_coordinate(coordinate, config.coordinate_decimals));
+    let expected_version = if_match_version(&headers)?;
+    let mut conn = get_conn(&pool).await?;
+
+    // The checks against the stored event and the update happen in one transaction so no other
+    // update can sneak in between them.
+    let (status, event) = conn.transaction::<_, Error, _>(|conn| {
+        let current = events::dsl::events
+            .filter(events::dsl::id.eq(id))
+            .first::<Event>(conn)
+            .optional()
+            .context("Failed to query event")?;
+
+        // PUT stores the event under the id it is sent to, so an id which was never used creates
+        // the event instead of being not found. Only deleted events, whose id stays taken, are not
+        // found, just like a DELETE of an id without an event.
+        let Some(current) = current else {
+            // There is nothing to match against when the event does not exist yet, not even `*`.
+            if expected_version.is_some() {
+                return Err(Error::PreconditionFailed);
+            }
+
+            // Sqlite hands out new ids after the largest existing one so an id picked by the
+            // client can't collide with ids assigned to posted events, as long as it is positive.
+            if id < 1 {
+                return Err(Error::OutOfRange {
+                    field: "id",
+                    minimum: 1,
+                    maximum: i64::MAX,
+                });
+            }
+
+            let new_event = req.into_event(id)?;
+            validate_event(&config, &new_event)?;
+
+            let event = diesel::insert_into(events::table)
+                .values(&new_event)
+                .get_result(conn)
+                .context("Failed to insert event")?;
+
+            debug!(id, "Created event via put");
+            return Ok((StatusCode::CREATED, event));
+        };
+
+        // The id of a deleted event stays taken until it is restored.
+        if current.deleted_at.is_some() {
+            return Err(Error::NotFound);
+        }
+
+        if let Some(IfMatch::Version(expected)) = expected_version {
+            if expected != current.version {
+                return Err(Error::PreconditionFailed);
+            }
+        }
+
+        // A request may only change some fields, e.g. a new start_date which is after the stored
+        // end_date, so we validate the event as it will be after the update.
+        let merged = req.merged(&current);
+        validate_event(&config, &merged)?;
+
+        let event = diesel::update(
+            events::dsl::events
+                .filter(events::dsl::id.eq(id))
+                .filter(events::dsl::deleted_at.is_null()),
+        )
+        .set((&req, events::dsl::version.eq(merged.version)))
+        .get_result(conn)
+        .optional()
+        .context("Failed to update event")?
+        .ok_or(Error::NotFound)?;
+
+        if query.enabled() {
+            dependency::check_order(conn, id)?;
+        }
+
+        Ok((StatusCode::OK, event))
+    })?;
+
+    Ok((status, Json(event)))
+}
+
+pub const MIN_PRIORITY: i64 = -100;
+pub const MAX_PRIORITY: i64 = 100;
+
+#[derive(Debug, Deserialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct PutEventPriority {
+    #[schema(example = 10)]
+    pub priority: i64,
+}
+
+/// Set the priority of an event
+#[utoipa::path(
+    put,
+    path = "/api/event/{id}/priority",
+    request_body = PutEventPriority,
+    responses(
+        (status = 200, description = "Updated the priority of an event", body = Event),
+        (status = 400, description = "Priority is out of range"),
+        (status = 404, description = "Event does not exist"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event"),
+    )
+)]
+pub async fn put_priority(
+    Path(id): Path<i64>,
+    Extension(pool): Extension<SqlitePool>,
+    req: Result<Json<PutEventPriority>, JsonRejection>,
 ) -> Result<Json<Event>, Error> {
     let Json(req) = req?;
-    let mut conn = pool.get().await.expect("can connect to sqlite");
+    if !(MIN_PRIORITY..=MAX_PRIORITY).contains(&req.priority) {
+        return Err(Error::OutOfRange {
+            field: "priority",
+            minimum: MIN_PRIORITY,
+            maximum: MAX_PRIORITY,
+        });
+    }
 
-    let event = diesel::update(events::dsl::events.filter(events::dsl::id.eq(id)))
-        .set(&req)
+    let mut conn = get_conn(&pool).await?;
+    let event = diesel::update(
+        events::dsl::events
+            .filter(events::dsl::id.eq(id))
+            .filter(events::dsl::deleted_at.is_null()),
+    )
+    .set((
+        events::dsl::priority.eq(req.priority),
+        events::dsl::edited_at.eq(unix_timestamp()),
+        events::dsl::version.eq(events::dsl::version + 1),
+    ))
+    .get_result(&mut *conn)
+    .optional()
+    .context("Failed to update event priority")?
+    .ok_or(Error::NotFound)?;
+
+    debug!(id, priority = req.priority, "Updated event priority");
+    Ok(Json(event))
+}
+
+/// Publish a draft so it shows up in the event lists
+#[utoipa::path(
+    post,
+    path = "/api/event/{id}/publish",
+    responses(
+        (status = 200, description = "The event is no longer a draft", body = Event),
+        (status = 404, description = "Event does not exist"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event"),
+    )
+)]
+pub async fn post_publish(
+    Path(id): Path<i64>,
+    Extension(pool): Extension<SqlitePool>,
+) -> Result<Json<Event>, Error> {
+    let mut conn = get_conn(&pool).await?;
+    let event = diesel::update(
+        events::dsl::events
+            .filter(events::dsl::id.eq(id))
+            .filter(events::dsl::deleted_at.is_null()),
+    )
+    .set((
+        events::dsl::draft.eq(false),
+        events::dsl::edited_at.eq(unix_timestamp()),
+        events::dsl::version.eq(events::dsl::version + 1),
+    ))
+    .get_result(&mut *conn)
+    .optional()
+    .context("Failed to publish event")?
+    .ok_or(Error::NotFound)?;
+
+    debug!(id, "Published event");
+    Ok(Json(event))
+}
+
+pub fn event_exists(conn: &mut SqliteConnection, id: i64) -> Result<bool, Error> {
+    let count: i64 = events::dsl::events
+        .filter(events::dsl::id.eq(id))
+        .filter(events::dsl::deleted_at.is_null())
+        .count()
+        .get_result(conn)
+        .context("Failed to query event")?;
+
+    Ok(count > 0)
+}
+
+// Counts the published events of every owner in one grouped query, unowned events are counted
+// under `None`. Without a window every event is counted.
+pub fn count_by_owner(
+    conn: &mut SqliteConnection,
+    window: Option<(i64, i64)>,
+) -> Result<Vec<(Option<String>, i64)>, Error> {
+    let (from, to) = window.unwrap_or((i64::MIN, i64::MAX));
+    let counts = events::dsl::events
+        .filter(events::dsl::deleted_at.is_null())
+        .filter(events::dsl::draft.eq(false))
+        .filter(events::dsl::start_date.lt(to))
+        .filter(events::dsl::end_date.gt(from))
+        .group_by(events::dsl::owner)
+        .select((events::dsl::owner, diesel::dsl::count_star()))
+        .load(conn)
+        .context("Failed to count events by owner")?;
+
+    Ok(counts)
+}
+
+// Counts the published events of a single owner.
+pub fn count_owned(conn: &mut SqliteConnection, owner: &str) -> Result<i64, Error> {
+    let count = events::dsl::events
+        .filter(events::dsl::owner.eq(owner))
+        .filter(events::dsl::deleted_at.is_null())
+        .filter(events::dsl::draft.eq(false))
+        .count()
+        .get_result(conn)
+        .context("Failed to count events of owner")?;
+
+    Ok(count)
+}
+
+// Loads the published events of a single owner, ordered by start date.
+pub fn load_owned(conn: &mut SqliteConnection, owner: &str) -> Result<Vec<Event>, Error> {
+    let events = events::dsl::events
+        .filter(events::dsl::owner.eq(owner))
+        .filter(events::dsl::deleted_at.is_null())
+        .filter(events::dsl::draft.eq(false))
+        .order((events::dsl::start_date.asc(), events::dsl::id.asc()))
+        .load(conn)
+        .context("Failed to load events of owner")?;
+
+    Ok(events)
+}
+
+// Loads every published event overlapping the window between `from` and `to`, ordered by start
+// date. Recurring events are replaced by their occurrences within the window, so they show up and
+// take up time the same way as in `get_all`. With an owner only their events are loaded, usernames
+// are compared ignoring case.
+pub fn load_window(
+    conn: &mut SqliteConnection,
+    from: i64,
+    to: i64,
+    owner: Option<&str>,
+) -> Result<Vec<Event>, Error> {
+    // A recurring event can have occurrences in the window even when its first one ended before.
+    let mut events_query = events::dsl::events
+        .filter(events::dsl::start_date.lt(to))
+        .filter(events::dsl::deleted_at.is_null())
+        .filter(events::dsl::draft.eq(false))
+        .filter(
+            events::dsl::end_date
+                .gt(from)
+                .or(events::dsl::recurrence.is_not_null()),
+        )
+        .order((events::dsl::start_date.asc(), events::dsl::id.asc()))
+        .into_boxed();
+    if let Some(owner) = owner {
+        events_query = events_query.filter(events::dsl::owner.eq(owner));
+    }
+
+    let events = events_query
+        .load(conn)
+        .context("Failed to load events in window")?;
+    let mut events = expand_recurrences(events, from, to);
+    events.sort_by_key(|event| (event.start_date, event.id));
+
+    Ok(events)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WindowQuery {
+    /// Unix timestamp of the start of the window.
+    #[param(example = 1690848000)]
+    pub from: i64,
+
+    /// Unix timestamp of the end of the window.
+    #[param(example = 1693526400)]
+    pub to: i64,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OwnerWindowQuery {
+    /// Unix timestamp of the start of the window.
+    #[param(example = 1690848000)]
+    pub from: i64,
+
+    /// Unix timestamp of the end of the window.
+    #[param(example = 1693526400)]
+    pub to: i64,
+
+    /// Only look at the events owned by this user.
+    #[param(example = "alice")]
+    pub owner: Option<String>,
+}
+
+/// A stretch of free time between two consecutive events.
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct EventGap {
+    #[schema(example = 1691236800)]
+    pub start: i64,
+
+    #[schema(example = 1691247600)]
+    pub end: i64,
+
+    #[schema(example = 10800)]
+    pub duration_seconds: i64,
+}
+
+/// Get the gaps between consecutive events in a window
+#[utoipa::path(
+    get,
+    path = "/api/event/gaps",
+    responses(
+        (status = 200, description = "Gaps between events are returned", body = [EventGap]),
+        (status = 400, description = "The window is invalid"),
+    ),
+    params(OwnerWindowQuery)
+)]
+pub async fn get_gaps(
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<OwnerWindowQuery>, QueryRejection>,
+) -> Result<Json<Vec<EventGap>>, Error> {
+    let Query(query) = query?;
+    check_bounded_window(&config, query.from, query.to)?;
+
+    let mut conn = get_conn(&pool).await?;
+    let events = load_window(&mut conn, query.from, query.to, query.owner.as_deref())?;
+
+    // Overlapping events are merged into one busy stretch, a gap only starts once every event
+    // seen so far has ended. Back to back events never produce a gap.
+    let mut gaps = Vec::new();
+    let mut busy_until: Option<i64> = None;
+    for event in events {
+        if let Some(previous_end) = busy_until {
+            if event.start_date > previous_end {
+                gaps.push(EventGap {
+                    start: previous_end,
+                    end: event.start_date,
+                    duration_seconds: event.start_date - previous_end,
+                });
+            }
+        }
+
+        busy_until = Some(busy_until.map_or(event.end_date, |end| end.max(event.end_date)));
+    }
+
+    debug!(count = gaps.len(), "Returning gaps between events");
+    Ok(Json(gaps))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct FirstSlotQuery {
+    /// Unix timestamp of the earliest start of the slot.
+    #[param(example = 1690848000)]
+    pub from: i64,
+
+    /// Unix timestamp the slot has to end by.
+    #[param(example = 1693526400)]
+    pub to: i64,
+
+    /// How long the slot has to be.
+    #[param(example = 3600)]
+    pub duration_seconds: u32,
+
+    /// Only the events owned by this user take up time, the slot may overlap events of others.
+    #[param(example = "alice")]
+    pub owner: Option<String>,
+}
+
+// Returns the start of the earliest stretch of `duration` seconds between `from` and `to` which
+// doesn't overlap any of the events, which have to be ordered by start date.
+fn first_free_slot(events: &[Event], from: i64, to: i64, duration: i64) -> Option<i64> {
+    let mut free_from = from;
+    for event in events {
+        if event.start_date - free_from >= duration {
+            break;
+        }
+
+        free_from = free_from.max(event.end_date);
+    }
+
+    (to - free_from >= duration).then_some(free_from)
+}
+
+// Like `first_free_slot` but the slot also has to fit within the working hours of a single day.
+fn first_free_slot_within(
+    events: &[Event],
+    from: i64,
+    to: i64,
+    duration: i64,
+    working_hours: &WorkingHours,
+) -> Option<i64> {
+    // Without this check we would look at every day until `to` without ever finding a slot.
+    if working_hours.longest_seconds() < duration {
+        return None;
+    }
+
+    let tz = working_hours.timezone();
+    let mut day = local_datetime(from, tz)?.date();
+    loop {
+        let (day_start, _) = day_bounds(day, tz)?;
+        if day_start >= to {
+            return None;
+        }
+
+        if let Some((start, end)) = working_hours.on(day) {
+            if let Some(slot) = first_free_slot(events, start.max(from), end.min(to), duration) {
+                return Some(slot);
+            }
+        }
+
+        day = day.next_day()?;
+    }
+}
+
+/// Get the earliest free slot of a given length in a window
+#[utoipa::path(
+    get,
+    path = "/api/event/first-slot",
+    responses(
+        (status = 200, description = "The earliest free slot", body = EventGap),
+        (status = 400, description = "The window or duration is invalid"),
+        (status = 404, description = "There is no free slot of that length in the window, or within the working hours when configured"),
+    ),
+    params(FirstSlotQuery)
+)]
+pub async fn get_first_slot(
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<FirstSlotQuery>, QueryRejection>,
+) -> Result<Json<EventGap>, Error> {
+    let Query(query) = query?;
+    check_bounded_window(&config, query.from, query.to)?;
+    if query.duration_seconds == 0 {
+        return Err(Error::OutOfRange {
+            field: "durationSeconds",
+            minimum: 1,
+            maximum: i64::from(u32::MAX),
+        });
+    }
+
+    let mut conn = get_conn(&pool).await?;
+    let events = load_window(&mut conn, query.from, query.to, query.owner.as_deref())?;
+    let duration = i64::from(query.duration_seconds);
+    let start = match &config.working_hours {
+        Some(working_hours) => {
+            first_free_slot_within(&events, query.from, query.to, duration, working_hours)
+        }
+        None => first_free_slot(&events, query.from, query.to, duration),
+    }
+    .ok_or(Error::NotFound)?;
+
+    debug!(start, duration, "Found first free slot");
+    Ok(Json(EventGap {
+        start,
+        end: start + duration,
+        duration_seconds: duration,
+    }))
+}
+
+/// The amount of scheduled time within a window.
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct TotalTime {
+    /// Seconds covered by at least one event, overlapping events are only counted once.
+    #[schema(example = 7200)]
+    pub total_seconds: i64,
+
+    /// The sum of every event's duration, overlapping time is counted once per event.
+    #[schema(example = 9000)]
+    pub raw_seconds: i64,
+}
+
+/// Get the total scheduled time in a window
+#[utoipa::path(
+    get,
+    path = "/api/event/total-time",
+    responses(
+        (status = 200, description = "Scheduled time is returned", body = TotalTime),
+        (status = 400, description = "The window is invalid"),
+    ),
+    params(OwnerWindowQuery)
+)]
+pub async fn get_total_time(
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<OwnerWindowQuery>, QueryRejection>,
+) -> Result<Json<TotalTime>, Error> {
+    let Query(query) = query?;
+    check_bounded_window(&config, query.from, query.to)?;
+
+    let mut conn = get_conn(&pool).await?;
+    let events = load_window(&mut conn, query.from, query.to, query.owner.as_deref())?;
+
+    // Events are clipped to the window so time outside of it is not counted. Since they are
+    // ordered by start date we can coalesce overlapping events in a single pass.
+    let mut total_seconds = 0;
+    let mut raw_seconds = 0;
+    let mut busy: Option<(i64, i64)> = None;
+    for event in events {
+        let start = event.start_date.max(query.from);
+        let end = event.end_date.min(query.to);
+        raw_seconds += end - start;
+
+        busy = match busy {
+            Some((busy_start, busy_end)) if start <= busy_end => {
+                Some((busy_start, busy_end.max(end)))
+            }
+            Some((busy_start, busy_end)) => {
+                total_seconds += busy_end - busy_start;
+                Some((start, end))
+            }
+            None => Some((start, end)),
+        };
+    }
+    if let Some((busy_start, busy_end)) = busy {
+        total_seconds += busy_end - busy_start;
+    }
+
+    debug!(total_seconds, raw_seconds, "Returning total scheduled time");
+    Ok(Json(TotalTime {
+        total_seconds,
+        raw_seconds,
+    }))
+}
+
+/// An event to be created relative to the end of another event.
+#[derive(Debug, Deserialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct PostEventAfter {
+    /// Seconds between the end of the referenced event and the start of the new one.
+    #[ts(rename = "gapSeconds")]
+    #[serde(rename = "gapSeconds")]
+    #[schema(example = 3600)]
+    pub gap_seconds: u32,
+
+    /// How long the new event lasts.
+    #[ts(rename = "durationSeconds")]
+    #[serde(rename = "durationSeconds")]
+    #[schema(example = 7200)]
+    pub duration_seconds: u32,
+
+    #[schema(example = "Big Mike")]
+    pub title: String,
+
+    #[schema(example = "We hike for 7 days in Norwegian plateau.")]
+    pub description: Option<String>,
+
+    #[schema(example = "#87d45d")]
+    pub color: Option<String>,
+
+    #[schema(example = 60.0520)]
+    pub location_lng: Option<f64>,
+
+    #[schema(example = 7.4142)]
+    pub location_lat: Option<f64>,
+
+    #[schema(example = "Hardangervidda")]
+    pub location_name: Option<String>,
+}
+
+/// Post an event starting after another event
+#[utoipa::path(
+    post,
+    path = "/api/event/{id}/after",
+    request_body = PostEventAfter,
+    responses(
+        (status = 200, description = "Posted an event", body = Event),
+        (status = 404, description = "The referenced event does not exist"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event to start after"),
+    )
+)]
+pub async fn post_after(
+    Path(id): Path<i64>,
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    req: Result<Json<PostEventAfter>, JsonRejection>,
+) -> Result<Json<Event>, Error> {
+    let Json(mut req) = req?;
+    check_text_fields(
+        Some(&req.title),
+        req.description.as_deref(),
+        req.location_name.as_deref(),
+    )?;
+    check_duration(&config, 0, i64::from(req.duration_seconds))?;
+    filter_words("title", Some(&mut req.title), config.word_filter.as_ref())?;
+    filter_words(
+        "description",
+        req.description.as_mut(),
+        config.word_filter.as_ref(),
+    )?;
+    validate_coordinates(req.location_lat, req.location_lng)?;
+    check_hex_color(req.color.as_deref())?;
+    check_palette(req.color.as_deref(), config.color_palette.as_deref())?;
+    let mut conn = get_conn(&pool).await?;
+
+    let reference_end: i64 = events::dsl::events
+        .filter(events::dsl::id.eq(id))
+        .filter(events::dsl::deleted_at.is_null())
+        .select(events::dsl::end_date)
+        .first(&mut *conn)
+        .optional()
+        .context("Failed to query referenced event")?
+        .ok_or(Error::NotFound)?;
+
+    let start_date = reference_end + i64::from(req.gap_seconds);
+    check_horizon(&config, start_date)?;
+    let new_event = PostEvent {
+        title: req.title,
+        description: req.description,
+        color: req.color,
+        start_date,
+        end_date: start_date + i64::from(req.duration_seconds),
+        location_lng: req
+            .location_lng
+            .map(|coordinate| round_coordinate(coordinate, config.coordinate_decimals)),
+        location_lat: req
+            .location_lat
+            .map(|coordinate| round_coordinate(coordinate, config.coordinate_decimals)),
+        location_name: req.location_name,
+        draft: false,
+        recurrence: None,
+        owner: None,
+        all_day: false,
+        timezone: None,
+        created_at: unix_timestamp(),
+    };
+
+    let event = diesel::insert_into(events::table)
+        .values(&new_event)
         .get_result(&mut *conn)
-        .context("Failed to update event")?;
+        .context("Failed to insert event")?;
+
+    debug!(
+        reference = id,
+        "Inserted event after reference successfully"
+    );
+    Ok(Json(event))
+}
+
+/// The events directly before and after an event by start date.
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct EventNeighbors {
+    pub previous: Option<Event>,
+    pub next: Option<Event>,
+}
+
+/// Get the events before and after an event
+#[utoipa::path(
+    get,
+    path = "/api/event/{id}/neighbors",
+    responses(
+        (status = 200, description = "Neighboring events are returned", body = EventNeighbors),
+        (status = 404, description = "Event does not exist"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event"),
+    )
+)]
+pub async fn get_neighbors(
+    Path(id): Path<i64>,
+    Extension(pool): Extension<SqlitePool>,
+) -> Result<Json<EventNeighbors>, Error> {
+    let mut conn = get_conn(&pool).await?;
+
+    let event = events::dsl::events
+        .filter(events::dsl::id.eq(id))
+        .filter(events::dsl::deleted_at.is_null())
+        .first::<Event>(&mut *conn)
+        .optional()
+        .context("Failed to query event")?
+        .ok_or(Error::NotFound)?;
+
+    // Events starting at the same time are ordered by their id so that every event has exactly
+    // one previous and one next event. Drafts aren't anyone's neighbor.
+    let previous = events::dsl::events
+        .filter(events::dsl::deleted_at.is_null())
+        .filter(events::dsl::draft.eq(false))
+        .filter(
+            events::dsl::start_date
+                .lt(event.start_date)
+                .or(events::dsl::start_date
+                    .eq(event.start_date)
+                    .and(events::dsl::id.lt(event.id))),
+        )
+        .order((events::dsl::start_date.desc(), events::dsl::id.desc()))
+        .first::<Event>(&mut *conn)
+        .optional()
+        .context("Failed to query previous event")?;
+
+    let next = events::dsl::events
+        .filter(events::dsl::deleted_at.is_null())
+        .filter(events::dsl::draft.eq(false))
+        .filter(
+            events::dsl::start_date
+                .gt(event.start_date)
+                .or(events::dsl::start_date
+                    .eq(event.start_date)
+                    .and(events::dsl::id.gt(event.id))),
+        )
+        .order((events::dsl::start_date.asc(), events::dsl::id.asc()))
+        .first::<Event>(&mut *conn)
+        .optional()
+        .context("Failed to query next event")?;
+
+    Ok(Json(EventNeighbors { previous, next }))
+}
+
+/// Get the events overlapping an event in time
+///
+/// Events which only touch, one ending when the other starts, don't overlap. Recurring events are
+/// compared by their first occurrence.
+#[utoipa::path(
+    get,
+    path = "/api/event/{id}/conflicts",
+    responses(
+        (status = 200, description = "Events overlapping the event, ordered by start date", body = [Event]),
+        (status = 404, description = "Event does not exist"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event"),
+    )
+)]
+pub async fn get_conflicts(
+    Path(id): Path<i64>,
+    Extension(pool): Extension<SqlitePool>,
+) -> Result<Json<Vec<Event>>, Error> {
+    let mut conn = get_conn(&pool).await?;
+
+    let event = events::dsl::events
+        .filter(events::dsl::id.eq(id))
+        .filter(events::dsl::deleted_at.is_null())
+        .first::<Event>(&mut *conn)
+        .optional()
+        .context("Failed to query event")?
+        .ok_or(Error::NotFound)?;
 
+    let conflicts = events::dsl::events
+        .filter(events::dsl::deleted_at.is_null())
+        .filter(events::dsl::draft.eq(false))
+        .filter(events::dsl::id.ne(event.id))
+        .filter(events::dsl::start_date.lt(event.end_date))
+        .filter(events::dsl::end_date.gt(event.start_date))
+        .order((events::dsl::start_date.asc(), events::dsl::id.asc()))
+        .load::<Event>(&mut *conn)
+        .context("Failed to query conflicting events")?;
+
+    debug!(id, count = conflicts.len(), "Returning conflicting events");
+    Ok(Json(conflicts))
+}
+
+/// An event together with how long it lasts.
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct EventWithDuration {
+    #[ts(flatten)]
+    #[serde(flatten)]
+    pub event: Event,
+
+    #[ts(rename = "durationSeconds")]
+    #[serde(rename = "durationSeconds")]
+    #[schema(example = 604800)]
+    pub duration_seconds: i64,
+}
+
+impl EventWithDuration {
+    fn new(event: Event) -> Self {
+        let duration_seconds = event.end_date - event.start_date;
+        EventWithDuration {
+            event,
+            duration_seconds,
+        }
+    }
+}
+
+/// The longest and shortest events within a window.
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct EventExtremes {
+    pub longest: Option<EventWithDuration>,
+    pub shortest: Option<EventWithDuration>,
+}
+
+/// Get the longest and shortest events in a window
+#[utoipa::path(
+    get,
+    path = "/api/event/extremes",
+    responses(
+        (status = 200, description = "The longest and shortest events are returned", body = EventExtremes),
+        (status = 400, description = "The window is invalid"),
+    ),
+    params(OwnerWindowQuery)
+)]
+pub async fn get_extremes(
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<OwnerWindowQuery>, QueryRejection>,
+) -> Result<Json<EventExtremes>, Error> {
+    let Query(query) = query?;
+    check_bounded_window(&config, query.from, query.to)?;
+
+    let mut conn = get_conn(&pool).await?;
+    let events = load_window(&mut conn, query.from, query.to, query.owner.as_deref())?;
+
+    // Events with the same duration are tie broken by the lowest id.
+    let duration = |event: &&Event| event.end_date - event.start_date;
+    let longest = events
+        .iter()
+        .min_by_key(|event| (Reverse(duration(event)), event.id))
+        .cloned()
+        .map(EventWithDuration::new);
+    let shortest = events
+        .iter()
+        .min_by_key(|event| (duration(event), event.id))
+        .cloned()
+        .map(EventWithDuration::new);
+
+    Ok(Json(EventExtremes { longest, shortest }))
+}
+
+/// An event together with how many other events it overlaps.
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct EventWithOverlaps {
+    #[ts(flatten)]
+    #[serde(flatten)]
+    pub event: Event,
+
+    #[ts(rename = "overlapCount")]
+    #[serde(rename = "overlapCount")]
+    #[schema(example = 2)]
+    pub overlap_count: u32,
+}
+
+// Counts for every event how many of the other events overlap it, the events have to be ordered by
+// start date. Instead of comparing every pair of events we count the events which start before an
+// event ends and subtract the ones which also ended before it started.
+fn count_overlaps(events: Vec<Event>) -> Vec<EventWithOverlaps> {
+    let starts: Vec<i64> = events.iter().map(|event| event.start_date).collect();
+    let mut ends: Vec<i64> = events.iter().map(|event| event.end_date).collect();
+    ends.sort_unstable();
+
+    // Events without a duration at the same instant end before each other start but don't start
+    // before each other end, so they have to be left out of the subtraction.
+    let mut instants: HashMap<i64, usize> = HashMap::new();
+    for event in events
+        .iter()
+        .filter(|event| event.start_date == event.end_date)
+    {
+        *instants.entry(event.start_date).or_default() += 1;
+    }
+
+    events
+        .into_iter()
+        .map(|event| {
+            let started = starts.partition_point(|&start| start < event.end_date);
+            let mut ended = ends.partition_point(|&end| end <= event.start_date);
+            if event.start_date == event.end_date {
+                ended -= instants[&event.start_date];
+            }
+
+            // An event with a duration overlaps itself.
+            let overlaps = started - ended - usize::from(event.start_date < event.end_date);
+            EventWithOverlaps {
+                event,
+                overlap_count: overlaps as u32,
+            }
+        })
+        .collect()
+}
+
+/// Get the events in a window with how many other events each of them overlaps
+#[utoipa::path(
+    get,
+    path = "/api/event/overlaps",
+    responses(
+        (status = 200, description = "The events in the window with their overlap count", body = [EventWithOverlaps]),
+        (status = 400, description = "The window is invalid"),
+    ),
+    params(OwnerWindowQuery)
+)]
+pub async fn get_overlaps(
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<OwnerWindowQuery>, QueryRejection>,
+) -> Result<Json<Vec<EventWithOverlaps>>, Error> {
+    let Query(query) = query?;
+    check_bounded_window(&config, query.from, query.to)?;
+
+    let mut conn = get_conn(&pool).await?;
+    let events = load_window(&mut conn, query.from, query.to, query.owner.as_deref())?;
+    let events = count_overlaps(events);
+    debug!(count = events.len(), "Returning events with overlaps");
+    Ok(Json(events))
+}
+
+/// The user an event is handed over to.
+#[derive(Debug, Deserialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct PostEventTransfer {
+    /// Username of the new owner of the event.
+    #[schema(example = "bob")]
+    pub owner: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct TransferQuery {
+    /// Refuse the transfer when the event overlaps any event of the new owner.
+    #[serde(default)]
+    #[param(example = true)]
+    pub check_conflicts: bool,
+}
+
+/// Move an event to the calendar of another user
+///
+/// With `checkConflicts=true` the event is only moved when it doesn't overlap any event of the new
+/// owner, otherwise the ids of the overlapping events are returned in `conflicts`.
+#[utoipa::path(
+    post,
+    path = "/api/event/{id}/transfer",
+    request_body = PostEventTransfer,
+    responses(
+        (status = 200, description = "The event belongs to the new owner", body = Event),
+        (status = 400, description = "The new owner does not exist"),
+        (status = 404, description = "Event does not exist"),
+        (status = 409, description = "The event overlaps events of the new owner"),
+    ),
+    params(
+        ("id" = i64, Path, description = "Identifier of the event"),
+        TransferQuery,
+    )
+)]
+pub async fn post_transfer(
+    Path(id): Path<i64>,
+    Extension(pool): Extension<SqlitePool>,
+    query: Result<Query<TransferQuery>, QueryRejection>,
+    req: Result<Json<PostEventTransfer>, JsonRejection>,
+) -> Result<Json<Event>, Error> {
+    let Query(query) = query?;
+    let Json(req) = req?;
+    let mut conn = get_conn(&pool).await?;
+
+    let event = conn.transaction::<_, Error, _>(|conn| {
+        let event = events::dsl::events
+            .filter(events::dsl::id.eq(id))
+            .filter(events::dsl::deleted_at.is_null())
+            .first::<Event>(conn)
+            .optional()
+            .context("Failed to query event")?
+            .ok_or(Error::NotFound)?;
+
+        let owner = owner_username(conn, &req.owner)?;
+
+        // Recurring events of the new owner block the event with each of their occurrences, the
+        // event itself is compared by its first occurrence like in `get_conflicts`.
+        if query.check_conflicts {
+            let mut conflicts: Vec<i64> =
+                load_window(conn, event.start_date, event.end_date, Some(&owner))?
+                    .into_iter()
+                    .map(|other| other.id)
+                    .filter(|&other| other != event.id)
+                    .collect();
+            conflicts.sort_unstable();
+            conflicts.dedup();
+            if !conflicts.is_empty() {
+                return Err(Error::OwnerConflict(conflicts));
+            }
+        }
+
+        let event = diesel::update(events::dsl::events.filter(events::dsl::id.eq(event.id)))
+            .set((
+                events::dsl::owner.eq(owner),
+                events::dsl::edited_at.eq(unix_timestamp()),
+                events::dsl::version.eq(events::dsl::version + 1),
+            ))
+            .get_result::<Event>(conn)
+            .context("Failed to transfer event")?;
+
+        Ok(event)
+    })?;
+
+    debug!(id, owner = ?event.owner, "Transferred event");
     Ok(Json(event))
 }
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// Only export events ending after this unix timestamp.
+    #[param(example = 1690848000)]
+    pub from: Option<i64>,
+
+    /// Only export events starting before this unix timestamp.
+    #[param(example = 1693526400)]
+    pub to: Option<i64>,
+}
+
+// How many events are loaded from the database at once while streaming an export.
+const EXPORT_CHUNK_SIZE: i64 = 500;
+
+fn ndjson_lines(events: &[Event]) -> anyhow::Result<String> {
+    let mut lines = String::new();
+    for event in events {
+        let line = serde_json::to_string(event).context("Failed to serialize event")?;
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+
+    Ok(lines)
+}
+
+/// Export events as newline delimited JSON
+#[utoipa::path(
+    get,
+    path = "/api/event/ndjson",
+    responses(
+        (status = 200, description = "Events are streamed as one JSON object per line", body = Event, content_type = "application/x-ndjson"),
+        (status = 400, description = "The window is invalid"),
+    ),
+    params(ExportQuery)
+)]
+pub async fn get_ndjson(
+    Extension(pool): Extension<SqlitePool>,
+    query: Result<Query<ExportQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, Error> {
+    let Query(ExportQuery { from, to }) = query?;
+    if let (Some(from), Some(to)) = (from, to) {
+        check_window(from, to)?;
+    }
+
+    // Events are loaded in chunks ordered by id, each chunk continues after the last id of the
+    // previous one. This way only one chunk is held in memory at a time no matter how many events
+    // are exported, and the response starts streaming right away.
+    let stream = futures::stream::try_unfold(Some(0), move |after_id| {
+        let pool = pool.clone();
+        async move {
+            let Some(after_id) = after_id else {
+                return Ok::<_, anyhow::Error>(None);
+            };
+
+            let mut conn = pool
+                .get()
+                .await
+                .map_err(|e| anyhow!("Failed to get a database connection: {e}"))?;
+            let mut events_query = events::dsl::events
+                .filter(events::dsl::id.gt(after_id))
+                .filter(events::dsl::deleted_at.is_null())
+                .filter(events::dsl::draft.eq(false))
+                .order(events::dsl::id.asc())
+                .limit(EXPORT_CHUNK_SIZE)
+                .into_boxed();
+            if let Some(from) = from {
+                events_query = events_query.filter(events::dsl::end_date.gt(from));
+            }
+            if let Some(to) = to {
+                events_query = events_query.filter(events::dsl::start_date.lt(to));
+            }
+
+            let events: Vec<Event> = events_query
+                .load(&mut *conn)
+                .context("Failed to load events for export")?;
+            let Some(last) = events.last() else {
+                return Ok(None);
+            };
+
+            // A chunk that isn't full means there are no more events after it.
+            let next = (events.len() as i64 == EXPORT_CHUNK_SIZE).then_some(last.id);
+            Ok(Some((ndjson_lines(&events)?, next)))
+        }
+    });
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(stream),
+    ))
+}
+
+/// Subscribe to all events as an iCalendar feed
+#[utoipa::path(
+    get,
+    path = "/api/calendar.ics",
+    responses(
+        (status = 200, description = "All published events as one VCALENDAR", body = String, content_type = "text/calendar"),
+        (status = 400, description = "from is after to"),
+    ),
+    params(ExportQuery)
+)]
+pub async fn get_calendar_feed(
+    Extension(pool): Extension<SqlitePool>,
+    query: Result<Query<ExportQuery>, QueryRejection>,
+) -> Result<impl IntoResponse, Error> {
+    let Query(ExportQuery { from, to }) = query?;
+    if let (Some(from), Some(to)) = (from, to) {
+        check_window(from, to)?;
+    }
+
+    let mut conn = get_conn(&pool).await?;
+    let mut events_query = events::dsl::events
+        .filter(events::dsl::draft.eq(false))
+        .filter(events::dsl::deleted_at.is_null())
+        .order_by(events::dsl::id)
+        .into_boxed();
+    if let Some(from) = from {
+        events_query = events_query.filter(events::dsl::end_date.gt(from));
+    }
+    if let Some(to) = to {
+        events_query = events_query.filter(events::dsl::start_date.lt(to));
+    }
+
+    let events: Vec<Event> = events_query
+        .load(&mut *conn)
+        .context("Failed to load events for the feed")?;
+    let calendar = ics::write_calendar(&events)?;
+
+    debug!(count = events.len(), "Returning calendar feed");
+    Ok((
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        calendar,
+    ))
+}
+
+/// The formats events can be exported in by `post_export`.
+#[derive(Debug, Default, Clone, Copy, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Ndjson,
+    Csv,
+    Ics,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportFormatQuery {
+    /// The format to export the events in, defaults to `json`.
+    #[param(example = "csv")]
+    pub format: Option<ExportFormat>,
+}
+
+const CSV_HEADER: &str = "id,title,description,color,start_date,end_date,location_lng,\
+location_lat,location_name,created_at,edited_at,priority,version,draft,recurrence,owner,all_day,timezone";
+
+// Fields are only quoted when they have to be, quotes inside of them are doubled.
+// https://www.rfc-editor.org/rfc/rfc4180#section-2
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_optional<T: ToString>(value: &Option<T>) -> String {
+    value
+        .as_ref()
+        .map(|value| csv_field(&value.to_string()))
+        .unwrap_or_default()
+}
+
+pub fn write_csv(events: &[Event]) -> String {
+    let mut csv = String::new();
+    csv.push_str(CSV_HEADER);
+    csv.push_str("\r\n");
+
+    for event in events {
+        let fields = [
+            event.id.to_string(),
+            csv_field(&event.title),
+            csv_optional(&event.description),
+            csv_field(&event.color),
+            event.start_date.to_string(),
+            event.end_date.to_string(),
+            csv_optional(&event.location_lng),
+            csv_optional(&event.location_lat),
+            csv_optional(&event.location_name),
+            event.created_at.to_string(),
+            csv_optional(&event.edited_at),
+            event.priority.to_string(),
+            event.version.to_string(),
+            event.draft.to_string(),
+            csv_optional(&event.recurrence),
+            csv_optional(&event.owner),
+            event.all_day.to_string(),
+            csv_optional(&event.timezone),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+
+    csv
+}
+
+/// Export the events matching a query
+///
+/// Takes the same filters as `GET /api/event` in the body, for queries which don't fit in a query
+/// string. All matching events are exported, `limit`, `offset`, `format`, `idsOnly` and `explain`
+/// are ignored.
+#[utoipa::path(
+    post,
+    path = "/api/event/export",
+    request_body = EventQuery,
+    responses(
+        (status = 200, description = "The matching events in the requested format", content(
+            ("application/json" = [Event]),
+            ("application/x-ndjson" = Event),
+            ("text/csv" = String),
+            ("text/calendar" = String),
+        )),
+        (status = 400, description = "The query is invalid"),
+    ),
+    params(ExportFormatQuery)
+)]
+pub async fn post_export(
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    format: Result<Query<ExportFormatQuery>, QueryRejection>,
+    query: Result<Json<EventQuery>, JsonRejection>,
+) -> Result<impl IntoResponse, Error> {
+    let Query(ExportFormatQuery { format }) = format?;
+    let Json(query) = query?;
+    let (tz, group_colors) = check_event_query(&config, &query)?;
+
+    let mut conn = get_conn(&pool).await?;
+    debug!(?query, ?format, "Exporting events");
+
+    let now = unix_timestamp();
+    let events: Vec<Event> = sort_events(
+        filter_events(&query, group_colors),
+        query.sort.unwrap_or_default(),
+        now,
+    )
+    .load(&mut *conn)
+    .context("Failed to load events for export")?;
+    let events = filter_loaded_events(events, &query, tz, now);
+
+    debug!(count = events.len(), "Returning exported events");
+    let (content_type, body) = match format.unwrap_or_default() {
+        ExportFormat::Json => (
+            "application/json",
+            serde_json::to_string(&events).context("Failed to serialize events")?,
+        ),
+        ExportFormat::Ndjson => ("application/x-ndjson", ndjson_lines(&events)?),
+        ExportFormat::Csv => ("text/csv; charset=utf-8", write_csv(&events)),
+        ExportFormat::Ics => (
+            "text/calendar; charset=utf-8",
+            ics::write_calendar(&events)?,
+        ),
+    };
+
+    Ok(([(header::CONTENT_TYPE, content_type)], body))
+}
+
+#[derive(Debug, Deserialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct EventShift {
+    #[schema(example = 1)]
+    pub id: i64,
+
+    /// Seconds to move the event by, negative values move it earlier.
+    #[schema(example = 3600)]
+    pub offset_seconds: i64,
+}
+
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct ShiftedEvents {
+    pub updated: Vec<Event>,
+
+    /// Ids of requested events which do not exist.
+    #[schema(example = json!([4]))]
+    pub not_found: Vec<i64>,
+}
+
+/// Move multiple events by individual offsets
+#[utoipa::path(
+    post,
+    path = "/api/event/batch/shift",
+    request_body = [EventShift],
+    responses(
+        (status = 200, description = "Events were moved", body = ShiftedEvents),
+        (status = 409, description = "An event would start before an event it depends on ends"),
+    ),
+    params(DependencyCheckQuery)
+)]
+pub async fn post_batch_shift(
+    Extension(pool): Extension<SqlitePool>,
+    query: Result<Query<DependencyCheckQuery>, QueryRejection>,
+    req: Result<Json<Vec<EventShift>>, JsonRejection>,
+) -> Result<Json<ShiftedEvents>, Error> {
+    let Query(query) = query?;
+    let Json(shifts) = req?;
+    let mut conn = get_conn(&pool).await?;
+    let edited_at = unix_timestamp();
+
+    // All events are moved together or not at all.
+    let shifted = conn.transaction::<_, Error, _>(|conn| {
+        let mut shifted = ShiftedEvents {
+            updated: Vec::new(),
+            not_found: Vec::new(),
+        };
+
+        for shift in shifts {
+            let event = diesel::update(
+                events::dsl::events
+                    .filter(events::dsl::id.eq(shift.id))
+                    .filter(events::dsl::deleted_at.is_null()),
+            )
+            .set((
+                events::dsl::start_date.eq(events::dsl::start_date + shift.offset_seconds),
+                events::dsl::end_date.eq(events::dsl::end_date + shift.offset_seconds),
+                events::dsl::edited_at.eq(edited_at),
+                events::dsl::version.eq(events::dsl::version + 1),
+            ))
+            .get_result::<Event>(conn)
+            .optional()
+            .context("Failed to shift event")?;
+
+            match event {
+                Some(event) => shifted.updated.push(event),
+                None => shifted.not_found.push(shift.id),
+            }
+        }
+
+        // The order is only checked once every event has moved, events shifted together by the
+        // same offset keep their order.
+        if query.enabled() {
+            for event in &shifted.updated {
+                dependency::check_order(conn, event.id)?;
+            }
+        }
+
+        Ok(shifted)
+    })?;
+
+    debug!(
+        updated = shifted.updated.len(),
+        not_found = shifted.not_found.len(),
+        "Shifted events"
+    );
+    Ok(Json(shifted))
+}
+
+/// The size of the buckets in a heatmap.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HeatmapBucket {
+    Hour,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HeatmapQuery {
+    /// Unix timestamp of the start of the window.
+    #[param(example = 1690848000)]
+    pub from: i64,
+
+    /// Unix timestamp of the end of the window.
+    #[param(example = 1693526400)]
+    pub to: i64,
+
+    /// IANA timezone the buckets are computed in, defaults to DEFAULT_TIMEZONE or UTC.
+    #[param(example = "Europe/Oslo")]
+    pub tz: Option<String>,
+
+    #[param(example = "hour")]
+    pub bucket: Option<HeatmapBucket>,
+}
+
+/// Event counts per hour of each day of the week.
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct Heatmap {
+    /// Indexed by the day of the week starting with Monday and then by the hour of the day.
+    #[schema(example = json!([[0, 1, 2]]))]
+    pub counts: Vec<Vec<u32>>,
+}
+
+/// Get a heatmap of when events take place
+#[utoipa::path(
+    get,
+    path = "/api/event/heatmap",
+    responses(
+        (status = 200, description = "The heatmap is returned", body = Heatmap),
+        (status = 400, description = "The window or timezone is invalid"),
+    ),
+    params(HeatmapQuery)
+)]
+pub async fn get_heatmap(
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<HeatmapQuery>, QueryRejection>,
+) -> Result<Json<Heatmap>, Error> {
+    let Query(query) = query?;
+    check_bounded_window(&config, query.from, query.to)?;
+    let tz = request_timezone(&config, query.tz.as_deref())?;
+
+    let mut conn = get_conn(&pool).await?;
+    let events = load_window(&mut conn, query.from, query.to, None)?;
+
+    // Hour is the only bucket size for now, an event counts once towards every hour it spans
+    // within the window so long events show up across the whole time they take.
+    let bucket_seconds = match query.bucket.unwrap_or(HeatmapBucket::Hour) {
+        HeatmapBucket::Hour => 3600,
+    };
+    let mut counts = vec![vec![0; 24]; 7];
+    for event in events {
+        let start = event.start_date.max(query.from);
+        let end = event.end_date.min(query.to);
+
+        let mut time = start;
+        loop {
+            let Some(date) = local_datetime(time, tz) else {
+                break;
+            };
+            counts[date.weekday().number_days_from_monday() as usize][date.hour() as usize] += 1;
+
+            // Continue at the start of the next local hour. Events without a duration still
+            // count towards the hour they start in.
+            time += bucket_seconds - i64::from(date.minute()) * 60 - i64::from(date.second());
+            if time >= end {
+                break;
+            }
+        }
+    }
+
+    Ok(Json(Heatmap { counts }))
+}
+
+/// The number of events of one owner.
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct OwnerCount {
+    /// Username of the owner, `null` for the events without an owner.
+    #[schema(example = "alice")]
+    pub owner: Option<String>,
+
+    #[schema(example = 12)]
+    pub count: i64,
+}
+
+/// Count the events in a window per owner
+///
+/// Only owners with at least one event in the window are returned, the busiest first.
+#[utoipa::path(
+    get,
+    path = "/api/event/by-owner",
+    responses(
+        (status = 200, description = "Event counts per owner are returned", body = [OwnerCount]),
+        (status = 400, description = "The window is invalid"),
+    ),
+    params(WindowQuery)
+)]
+pub async fn get_by_owner(
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<WindowQuery>, QueryRejection>,
+) -> Result<Json<Vec<OwnerCount>>, Error> {
+    let Query(query) = query?;
+    check_bounded_window(&config, query.from, query.to)?;
+
+    let mut conn = get_conn(&pool).await?;
+    let mut counts: Vec<OwnerCount> = count_by_owner(&mut conn, Some((query.from, query.to)))?
+        .into_iter()
+        .map(|(owner, count)| OwnerCount { owner, count })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.owner.cmp(&b.owner)));
+
+    debug!(owners = counts.len(), "Returning event counts per owner");
+    Ok(Json(counts))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CreatedOnQuery {
+    /// The calendar day the events were created on.
+    #[param(example = "2023-08-05")]
+    pub date: String,
+
+    /// IANA timezone the day is in, defaults to DEFAULT_TIMEZONE or UTC.
+    #[param(example = "Europe/Oslo")]
+    pub tz: Option<String>,
+}
+
+/// Get the events which were created on a calendar day
+#[utoipa::path(
+    get,
+    path = "/api/event/created-on",
+    responses(
+        (status = 200, description = "Events created on the day, oldest first", body = [Event]),
+        (status = 400, description = "The date or timezone is invalid"),
+    ),
+    params(CreatedOnQuery)
+)]
+pub async fn get_created_on(
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<CreatedOnQuery>, QueryRejection>,
+) -> Result<Json<Vec<Event>>, Error> {
+    let Query(query) = query?;
+    let tz = request_timezone(&config, query.tz.as_deref())?;
+    let date = parse_date(&query.date)?;
+    let (start, end) =
+        day_bounds(date, tz).ok_or_else(|| Error::InvalidDate(query.date.clone()))?;
+
+    let mut conn = get_conn(&pool).await?;
+    let events = events::dsl::events
+        .filter(events::dsl::created_at.ge(start))
+        .filter(events::dsl::deleted_at.is_null())
+        .filter(events::dsl::draft.eq(false))
+        .filter(events::dsl::created_at.lt(end))
+        .order_by((events::dsl::created_at, events::dsl::id))
+        .load::<Event>(&mut *conn)
+        .context("Failed to query events")?;
+
+    Ok(Json(events))
+}
+
+/// The color imported events get when no other color is requested.
+pub const IMPORT_COLOR: &str = "#87d45d";
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    /// Color given to all imported events since iCalendar files don't carry one, defaults to
+    /// `#87d45d`.
+    #[param(example = "#87d45d")]
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct ImportedEvents {
+    /// How many events were created.
+    #[schema(example = 12)]
+    pub imported: u32,
+
+    /// VEVENTs which were not imported and why.
+    pub skipped: Vec<IcsEventReport>,
+}
+
+// Turns a parsed VEVENT into an event, applying the same checks as `post`.
+fn import_event(config: &Config, event: IcsEvent, color: &str) -> Result<PostEvent, Error> {
+    let mut new_event = PostEvent {
+        title: event.summary,
+        description: event.description,
+        color: Some(color.to_string()),
+        start_date: event.start_date,
+        end_date: event.end_date,
+        location_lng: None,
+        location_lat: None,
+        location_name: event.location,
+        draft: false,
+        recurrence: event.recurrence,
+        owner: None,
+        all_day: event.all_day,
+        timezone: None,
+        created_at: unix_timestamp(),
+    };
+
+    check_text_fields(
+        Some(&new_event.title),
+        new_event.description.as_deref(),
+        new_event.location_name.as_deref(),
+    )?;
+    check_date_range(new_event.start_date, new_event.end_date)?;
+    check_duration(config, new_event.start_date, new_event.end_date)?;
+    check_horizon(config, new_event.start_date)?;
+    check_recurrence(new_event.recurrence.as_deref())?;
+    filter_words(
+        "title",
+        Some(&mut new_event.title),
+        config.word_filter.as_ref(),
+    )?;
+    filter_words(
+        "description",
+        new_event.description.as_mut(),
+        config.word_filter.as_ref(),
+    )?;
+
+    Ok(new_event)
+}
+
+/// Import the events of an iCalendar file
+#[utoipa::path(
+    post,
+    path = "/api/event/import",
+    request_body(content = String, description = "An iCalendar file", content_type = "text/calendar"),
+    responses(
+        (status = 200, description = "The importable events were created", body = ImportedEvents),
+        (status = 400, description = "The body is not an iCalendar file or the color is invalid"),
+        (status = 415, description = "The body was not sent as text/calendar"),
+    ),
+    params(ImportQuery)
+)]
+pub async fn import_ics(
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<ImportQuery>, QueryRejection>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<ImportedEvents>, Error> {
+    let Query(query) = query?;
+    ics::check_content_type(&headers)?;
+    let color = query.color.unwrap_or_else(|| IMPORT_COLOR.to_string());
+    check_hex_color(Some(&color))?;
+    check_palette(Some(&color), config.color_palette.as_deref())?;
+
+    let mut new_events = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, parsed) in ics::parse_calendar(&body)?.into_iter().enumerate() {
+        let new_event = parsed.event.and_then(|event| {
+            import_event(&config, event, &color).map_err(|e| vec![e.to_string()])
+        });
+
+        match new_event {
+            Ok(new_event) => new_events.push(new_event),
+            Err(problems) => skipped.push(IcsEventReport {
+                index: index as u32,
+                uid: parsed.uid,
+                summary: parsed.summary,
+                event: None,
+                problems,
+            }),
+        }
+    }
+
+    // Either all importable events are created or none of them.
+    let mut conn = get_conn(&pool).await?;
+    conn.transaction::<_, Error, _>(|conn| {
+        for new_event in &new_events {
+            diesel::insert_into(events::table)
+                .values(new_event)
+                .execute(conn)
+                .context("Failed to insert imported event")?;
+        }
+
+        Ok(())
+    })?;
+
+    debug!(
+        imported = new_events.len(),
+        skipped = skipped.len(),
+        "Imported iCalendar file"
+    );
+    Ok(Json(ImportedEvents {
+        imported: new_events.len() as u32,
+        skipped,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(name: header::HeaderName, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn if_match_version_reads_versions_and_any() {
+        assert_eq!(if_match_version(&HeaderMap::new()).unwrap(), None);
+        assert_eq!(
+            if_match_version(&headers(header::IF_MATCH, "3")).unwrap(),
+            Some(IfMatch::Version(3))
+        );
+        assert_eq!(
+            if_match_version(&headers(header::IF_MATCH, " \"4\" ")).unwrap(),
+            Some(IfMatch::Version(4))
+        );
+        assert_eq!(
+            if_match_version(&headers(header::IF_MATCH, "*")).unwrap(),
+            Some(IfMatch::Any)
+        );
+        assert!(matches!(
+            if_match_version(&headers(header::IF_MATCH, "W/\"4\"")),
+            Err(Error::PreconditionFailed)
+        ));
+    }
+
+    #[test]
+    fn event_etag_depends_on_version_and_format() {
+        let event = Event {
+            id: 1,
+            title: "Hike".to_string(),
+            description: None,
+            color: "#87d45d".to_string(),
+            start_date: 1691226000,
+            end_date: 1691229600,
+            location_lng: None,
+            location_lat: None,
+            location_name: None,
+            created_at: 1691000000,
+            edited_at: None,
+            priority: 0,
+            version: 4,
+            draft: false,
+            recurrence: None,
+            owner: None,
+            deleted_at: None,
+            all_day: false,
+            timezone: None,
+        };
+
+        // Only stored fields go into it, computing it again gives the same tag.
+        let native = event_etag(&event, None).unwrap();
+        assert_eq!(native, "\"4\"");
+        assert_eq!(event_etag(&event, None).unwrap(), native);
+        assert_eq!(
+            event_etag(&event, Some(&EventDetailFormat::Jsonld)).unwrap(),
+            "\"4-jsonld\""
+        );
+    }
+
+    #[test]
+    fn if_none_match_compares_etags_weakly() {
+        let etag = HeaderValue::from_static("\"4\"");
+        assert!(!if_none_match(&HeaderMap::new(), &etag));
+        assert!(if_none_match(
+            &headers(header::IF_NONE_MATCH, "\"4\""),
+            &etag
+        ));
+        assert!(if_none_match(
+            &headers(header::IF_NONE_MATCH, "W/\"4\""),
+            &etag
+        ));
+        assert!(if_none_match(
+            &headers(header::IF_NONE_MATCH, "\"3\", \"4\""),
+            &etag
+        ));
+        assert!(if_none_match(&headers(header::IF_NONE_MATCH, "*"), &etag));
+        assert!(!if_none_match(
+            &headers(header::IF_NONE_MATCH, "\"3\""),
+            &etag
+        ));
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::{anyhow, Context};
+use axum::http::StatusCode;
+use axum::{Extension, Json};
+use diesel::prelude::*;
+use serde::Serialize;
+use tracing::error;
+use ts_rs::TS;
+use utoipa::ToSchema;
+
+use crate::SqlitePool;
+
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct Health {
+    /// Either `ok` or `unavailable`.
+    #[schema(example = "ok")]
+    pub status: &'static str,
+}
+
+/// Check whether the server can reach its database
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses(
+        (status = 200, description = "The database can be queried", body = Health),
+        (status = 503, description = "The database can't be reached", body = Health),
+    )
+)]
+// A missing connection is reported as unavailable rather than as an internal error, so probes can
+// tell the database apart from other failures.
+pub async fn get(Extension(pool): Extension<SqlitePool>) -> (StatusCode, Json<Health>) {
+    let result = match pool.get().await {
+        Ok(mut conn) => diesel::sql_query("SELECT 1")
+            .execute(&mut *conn)
+            .map(|_| ())
+            .context("Failed to query database"),
+        Err(e) => Err(anyhow!("Failed to get a database connection: {e}")),
+    };
+
+    match result {
+        Ok(()) => (StatusCode::OK, Json(Health { status: "ok" })),
+        Err(e) => {
+            error!("Health check failed: {e:#}");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(Health {
+                    status: "unavailable",
+                }),
+            )
+        }
+    }
+}
@@ -0,0 +1,706 @@
+// A small iCalendar (RFC 5545) parser which only understands as much as we need to import events.
+// https://www.rfc-editor.org/rfc/rfc5545
+use anyhow::Context;
+use axum::http::{header, HeaderMap};
+use axum::Json;
+use serde::Serialize;
+use time::format_description::FormatItem;
+use time::macros::format_description;
+use time::{Date, OffsetDateTime, PrimitiveDateTime, Time};
+use time_tz::{timezones, OffsetDateTimeExt, PrimitiveDateTimeExt, TimeZone, Tz};
+use tracing::debug;
+use ts_rs::TS;
+use utoipa::ToSchema;
+
+use crate::error::Error;
+use crate::event::Event;
+use crate::recurrence::Recurrence;
+
+pub const CONTENT_TYPE: &str = "text/calendar";
+
+pub const DATE: &[FormatItem<'static>] = format_description!("[year][month][day]");
+const DATE_TIME: &[FormatItem<'static>] =
+    format_description!("[year][month][day]T[hour][minute][second]");
+pub const UTC_DATE_TIME: &[FormatItem<'static>] =
+    format_description!("[year][month][day]T[hour][minute][second]Z");
+
+// Content lines should not be longer than this many bytes, excluding the line break.
+const MAX_LINE_LENGTH: usize = 75;
+
+/// A content line like `DTSTART;TZID=Europe/Oslo:20230805T090000`.
+#[derive(Debug)]
+pub struct Property {
+    pub name: String,
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+impl Property {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// An event read from a VEVENT which has everything needed to be imported.
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct IcsEvent {
+    #[schema(example = "Big Mike")]
+    pub summary: String,
+
+    #[schema(example = "Mike's birthday")]
+    pub description: Option<String>,
+
+    #[schema(example = "Oslo")]
+    pub location: Option<String>,
+
+    #[schema(example = 1691226000)]
+    pub start_date: i64,
+
+    #[schema(example = 1691229600)]
+    pub end_date: i64,
+
+    /// Whether DTSTART was a date without a time.
+    #[schema(example = false)]
+    pub all_day: bool,
+
+    #[schema(example = "FREQ=WEEKLY;COUNT=52")]
+    pub recurrence: Option<String>,
+}
+
+/// A VEVENT in the order it appeared in, with the problems preventing its import if there are any.
+#[derive(Debug)]
+pub struct ParsedEvent {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub event: Result<IcsEvent, Vec<String>>,
+}
+
+// Long lines are folded by breaking them and starting the continuation with a space or tab.
+fn unfold(body: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in body.lines() {
+        match (line.strip_prefix(&[' ', '\t'][..]), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines
+}
+
+fn parse_property(line: &str) -> Option<Property> {
+    // The value starts after the first colon which is not part of a quoted parameter value.
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let mut parts = head.split(';');
+    let name = parts.next()?.trim().to_ascii_uppercase();
+    let params = parts
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| {
+            (
+                key.to_ascii_uppercase(),
+                value.trim_matches('"').to_string(),
+            )
+        })
+        .collect();
+
+    Some(Property {
+        name,
+        params,
+        value: value.to_string(),
+    })
+}
+
+// Text values escape newlines, backslashes, semicolons and commas with a backslash.
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n' | 'N') => result.push('\n'),
+            Some(escaped) => result.push(escaped),
+            None => result.push('\\'),
+        }
+    }
+
+    result
+}
+
+// Returns the unix timestamp of a DATE or DATE-TIME value and whether it was a DATE.
+fn parse_date_time(property: &Property) -> Result<(i64, bool), String> {
+    let value = property.value.trim();
+    if property.param("VALUE") == Some("DATE") {
+        let date = Date::parse(value, DATE).map_err(|_| format!("{value} is not a date"))?;
+        return Ok((date.midnight().assume_utc().unix_timestamp(), true));
+    }
+
+    let (local, utc) = match value.strip_suffix('Z') {
+        Some(local) => (local, true),
+        None => (value, false),
+    };
+    let date_time = PrimitiveDateTime::parse(local, DATE_TIME)
+        .map_err(|_| format!("{value} is not a date-time"))?;
+
+    let timestamp = match (utc, property.param("TZID")) {
+        (false, Some(tzid)) => {
+            let tz = timezones::get_by_name(tzid).ok_or_else(|| format!("unknown TZID {tzid}"))?;
+            date_time
+                .assume_timezone(tz)
+                .take_first()
+                .ok_or_else(|| format!("{value} does not exist in {tzid}"))?
+                .unix_timestamp()
+        }
+        // Floating times which are not bound to a timezone are treated as UTC.
+        _ => date_time.assume_utc().unix_timestamp(),
+    };
+
+    Ok((timestamp, false))
+}
+
+// Parses durations like `PT1H30M` or `P1D` into seconds.
+fn parse_duration(value: &str) -> Option<i64> {
+    let (sign, value) = match value.trim().strip_prefix('-') {
+        Some(value) => (-1, value),
+        None => (1, value.trim().trim_start_matches('+')),
+    };
+
+    let mut seconds = 0;
+    let mut number = String::new();
+    let mut in_time = false;
+    for c in value.strip_prefix('P')?.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' if number.is_empty() && !in_time => in_time = true,
+            'W' | 'D' | 'H' | 'M' | 'S' => {
+                let unit = match (c, in_time) {
+                    ('W', false) => 7 * 24 * 60 * 60,
+                    ('D', false) => 24 * 60 * 60,
+                    ('H', true) => 60 * 60,
+                    ('M', true) => 60,
+                    ('S', true) => 1,
+                    _ => return None,
+                };
+                seconds += number.parse::<i64>().ok()? * unit;
+                number.clear();
+            }
+            _ => return None,
+        }
+    }
+
+    if !number.is_empty() {
+        return None;
+    }
+
+    Some(sign * seconds)
+}
+
+fn parse_event(properties: &[Property]) -> ParsedEvent {
+    let find = |name: &str| properties.iter().find(|property| property.name == name);
+    let text = |name: &str| find(name).map(|property| unescape(&property.value));
+
+    let uid = find("UID").map(|property| property.value.clone());
+    let summary = text("SUMMARY");
+    let mut problems = Vec::new();
+
+    if summary.is_none() {
+        problems.push("missing SUMMARY".to_string());
+    }
+
+    let recurrence = find("RRULE").map(|property| property.value.clone());
+    if let Some(recurrence) = &recurrence {
+        if recurrence.parse::<Recurrence>().is_err() {
+            problems.push(format!("unsupported RRULE: {recurrence}"));
+        }
+    }
+
+    let start = match find("DTSTART").map(parse_date_time) {
+        Some(Ok(start)) => Some(start),
+        Some(Err(problem)) => {
+            problems.push(format!("invalid DTSTART: {problem}"));
+            None
+        }
+        None => {
+            problems.push("missing DTSTART".to_string());
+            None
+        }
+    };
+
+    // Without DTEND the end is either given by DURATION or the event lasts the whole day for
+    // dates and no time at all for date-times.
+    let end = match (find("DTEND"), find("DURATION"), start) {
+        (Some(end), _, _) => match parse_date_time(end) {
+            Ok((end, _)) => Some(end),
+            Err(problem) => {
+                problems.push(format!("invalid DTEND: {problem}"));
+                None
+            }
+        },
+        (None, Some(duration), Some((start, _))) => match parse_duration(&duration.value) {
+            Some(duration) => Some(start + duration),
+            None => {
+                problems.push(format!("invalid DURATION: {}", duration.value));
+                None
+            }
+        },
+        (None, None, Some((start, true))) => Some(start + 24 * 60 * 60),
+        (None, None, Some((start, false))) => Some(start),
+        (None, _, None) => None,
+    };
+
+    let event = match (summary.clone(), start, end) {
+        (Some(_), Some((start, _)), Some(end)) if end < start => {
+            problems.push("DTEND is before DTSTART".to_string());
+            Err(problems)
+        }
+        (Some(summary), Some((start_date, all_day)), Some(end_date)) if problems.is_empty() => {
+            Ok(IcsEvent {
+                summary,
+                description: text("DESCRIPTION"),
+                location: text("LOCATION"),
+                start_date,
+                end_date,
+                all_day,
+                recurrence,
+            })
+        }
+        _ => Err(problems),
+    };
+
+    ParsedEvent {
+        uid,
+        summary,
+        event,
+    }
+}
+
+// Text values have to escape characters which are part of the iCalendar syntax.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// Appends a content line, folding it into multiple lines if it is too long. Lines are only broken
+// between characters so multi-byte characters are kept intact.
+fn push_line(calendar: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > MAX_LINE_LENGTH {
+            calendar.push_str("\r\n ");
+            // The space starting the continuation counts towards the length of the line.
+            length = 1;
+        }
+
+        calendar.push(c);
+        length += c.len_utf8();
+    }
+
+    calendar.push_str("\r\n");
+}
+
+fn utc_date_time(timestamp: i64) -> anyhow::Result<String> {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .context("Timestamp is out of range")?
+        .format(UTC_DATE_TIME)
+        .context("Failed to format timestamp")
+}
+
+// Events with a timezone are written in its local time so clients keep them at the same time of
+// day across DST changes.
+fn local_date_time(timestamp: i64, tz: &Tz) -> anyhow::Result<String> {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .context("Timestamp is out of range")?
+        .to_timezone(tz)
+        .format(DATE_TIME)
+        .context("Failed to format timestamp")
+}
+
+// All day events are written as the UTC dates they span. DTEND is exclusive, so an event ending
+// during a day includes that whole day and an event ending at midnight doesn't include the next.
+fn all_day_dates(start_date: i64, end_date: i64) -> anyhow::Result<(String, String)> {
+    let start =
+        OffsetDateTime::from_unix_timestamp(start_date).context("Timestamp is out of range")?;
+    let end = OffsetDateTime::from_unix_timestamp(end_date).context("Timestamp is out of range")?;
+
+    let mut end_day = end.date();
+    if end.time() != Time::MIDNIGHT || end_day <= start.date() {
+        end_day = end_day.next_day().context("Timestamp is out of range")?;
+    }
+
+    Ok((
+        start.date().format(DATE).context("Failed to format date")?,
+        end_day.format(DATE).context("Failed to format date")?,
+    ))
+}
+
+// UIDs only depend on the event id so that clients recognize the same event across exports.
+pub fn event_uid(id: i64) -> String {
+    format!("event-{id}@calendar.mavulp")
+}
+
+fn push_event(calendar: &mut String, event: &Event) -> anyhow::Result<()> {
+    push_line(calendar, "BEGIN:VEVENT");
+    push_line(calendar, &format!("UID:{}", event_uid(event.id)));
+    let stamp = event.edited_at.unwrap_or(event.created_at);
+    push_line(calendar, &format!("DTSTAMP:{}", utc_date_time(stamp)?));
+    if event.all_day {
+        let (start, end) = all_day_dates(event.start_date, event.end_date)?;
+        push_line(calendar, &format!("DTSTART;VALUE=DATE:{start}"));
+        push_line(calendar, &format!("DTEND;VALUE=DATE:{end}"));
+    } else if let Some(tz) = event.timezone.as_deref().and_then(timezones::get_by_name) {
+        let tzid = tz.name();
+        push_line(
+            calendar,
+            &format!(
+                "DTSTART;TZID={tzid}:{}",
+                local_date_time(event.start_date, tz)?
+            ),
+        );
+        push_line(
+            calendar,
+            &format!("DTEND;TZID={tzid}:{}", local_date_time(event.end_date, tz)?),
+        );
+    } else {
+        push_line(
+            calendar,
+            &format!("DTSTART:{}", utc_date_time(event.start_date)?),
+        );
+        push_line(
+            calendar,
+            &format!("DTEND:{}", utc_date_time(event.end_date)?),
+        );
+    }
+    push_line(calendar, &format!("SUMMARY:{}", escape(&event.title)));
+
+    if let Some(description) = &event.description {
+        push_line(calendar, &format!("DESCRIPTION:{}", escape(description)));
+    }
+
+    if let Some(location) = &event.location_name {
+        push_line(calendar, &format!("LOCATION:{}", escape(location)));
+    }
+
+    if let (Some(lat), Some(lng)) = (event.location_lat, event.location_lng) {
+        push_line(calendar, &format!("GEO:{lat};{lng}"));
+    }
+
+    if let Some(recurrence) = &event.recurrence {
+        push_line(calendar, &format!("RRULE:{recurrence}"));
+    }
+
+    push_line(calendar, "END:VEVENT");
+    Ok(())
+}
+
+/// Writes the events as one VCALENDAR, timestamps are written in UTC unless the event has a
+/// timezone.
+pub fn write_calendar<'a>(events: impl IntoIterator<Item = &'a Event>) -> anyhow::Result<String> {
+    let mut calendar = String::new();
+    push_line(&mut calendar, "BEGIN:VCALENDAR");
+    push_line(&mut calendar, "VERSION:2.0");
+    push_line(&mut calendar, "PRODID:-//Mavulp//calendar//EN");
+
+    for event in events {
+        push_event(&mut calendar, event)?;
+    }
+
+    push_line(&mut calendar, "END:VCALENDAR");
+    Ok(calendar)
+}
+
+/// Reads every VEVENT of a VCALENDAR, properties of components nested in events like VALARM are
+/// ignored.
+pub fn parse_calendar(body: &str) -> Result<Vec<ParsedEvent>, Error> {
+    let lines = unfold(body);
+    if !lines
+        .iter()
+        .any(|line| line.trim().eq_ignore_ascii_case("BEGIN:VCALENDAR"))
+    {
+        return Err(Error::InvalidCalendar);
+    }
+
+    let mut events = Vec::new();
+    let mut current: Option<Vec<Property>> = None;
+    let mut nested = 0;
+    for line in lines {
+        let Some(property) = parse_property(&line) else {
+            continue;
+        };
+
+        let Some(properties) = current.as_mut() else {
+            if property.name == "BEGIN" && property.value.eq_ignore_ascii_case("VEVENT") {
+                current = Some(Vec::new());
+            }
+            continue;
+        };
+
+        match property.name.as_str() {
+            "BEGIN" => nested += 1,
+            "END" if nested > 0 => nested -= 1,
+            "END" => {
+                events.push(parse_event(properties));
+                current = None;
+            }
+            _ if nested == 0 => properties.push(property),
+            _ => {}
+        }
+    }
+
+    Ok(events)
+}
+
+pub fn check_content_type(headers: &HeaderMap) -> Result<(), Error> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(str::trim);
+
+    match content_type {
+        Some(content_type) if content_type.eq_ignore_ascii_case(CONTENT_TYPE) => Ok(()),
+        _ => Err(Error::UnsupportedMediaType(CONTENT_TYPE)),
+    }
+}
+
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct IcsEventReport {
+    /// Position of the VEVENT in the file, starting at 0.
+    #[schema(example = 0)]
+    pub index: u32,
+
+    #[schema(example = "2b8f3a4e@example.com")]
+    pub uid: Option<String>,
+
+    #[schema(example = "Big Mike")]
+    pub summary: Option<String>,
+
+    /// The event as it would be imported, missing for invalid events.
+    pub event: Option<IcsEvent>,
+
+    /// Reasons the event can't be imported, empty for importable events.
+    #[schema(example = json!(["missing DTSTART"]))]
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct IcsValidation {
+    /// How many VEVENTs the file contains.
+    #[schema(example = 3)]
+    pub found: u32,
+
+    pub importable: Vec<IcsEventReport>,
+
+    pub invalid: Vec<IcsEventReport>,
+}
+
+/// Check which events of an iCalendar file could be imported without importing them
+#[utoipa::path(
+    post,
+    path = "/api/event/ics/validate",
+    request_body(content = String, description = "An iCalendar file", content_type = "text/calendar"),
+    responses(
+        (status = 200, description = "Report of the events in the file", body = IcsValidation),
+        (status = 400, description = "The body is not an iCalendar file"),
+        (status = 415, description = "The body was not sent as text/calendar"),
+    )
+)]
+pub async fn post_validate(headers: HeaderMap, body: String) -> Result<Json<IcsValidation>, Error> {
+    check_content_type(&headers)?;
+    let events = parse_calendar(&body)?;
+
+    let mut validation = IcsValidation {
+        found: events.len() as u32,
+        importable: Vec::new(),
+        invalid: Vec::new(),
+    };
+
+    for (index, parsed) in events.into_iter().enumerate() {
+        let (event, problems) = match parsed.event {
+            Ok(event) => (Some(event), Vec::new()),
+            Err(problems) => (None, problems),
+        };
+
+        let importable = event.is_some();
+        let report = IcsEventReport {
+            index: index as u32,
+            uid: parsed.uid,
+            summary: parsed.summary,
+            event,
+            problems,
+        };
+
+        if importable {
+            validation.importable.push(report);
+        } else {
+            validation.invalid.push(report);
+        }
+    }
+
+    debug!(
+        found = validation.found,
+        invalid = validation.invalid.len(),
+        "Validated iCalendar file"
+    );
+    Ok(Json(validation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties(lines: &[&str]) -> Vec<Property> {
+        lines
+            .iter()
+            .map(|line| parse_property(line).expect("Invalid content line"))
+            .collect()
+    }
+
+    #[test]
+    fn unfold_joins_continuation_lines() {
+        let body = "SUMMARY:A long\r\n  summary\r\n\tcontinued\r\nDTSTART:20230805T090000Z\r\n";
+        assert_eq!(
+            unfold(body),
+            [
+                "SUMMARY:A long summarycontinued",
+                "DTSTART:20230805T090000Z"
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_property_reads_params_and_value() {
+        let property = parse_property("dtstart;TZID=\"Europe/Oslo\":20230805T110000").unwrap();
+        assert_eq!(property.name, "DTSTART");
+        assert_eq!(property.param("tzid"), Some("Europe/Oslo"));
+        assert_eq!(property.value, "20230805T110000");
+    }
+
+    #[test]
+    fn parse_property_ignores_quoted_colons() {
+        let property = parse_property("ATTENDEE;CN=\"Doe: John\":mailto:john@example.com").unwrap();
+        assert_eq!(property.param("CN"), Some("Doe: John"));
+        assert_eq!(property.value, "mailto:john@example.com");
+        assert!(parse_property("no colon").is_none());
+    }
+
+    #[test]
+    fn parse_event_reads_timezone_and_duration() {
+        let parsed = parse_event(&properties(&[
+            "UID:hike@example.com",
+            "SUMMARY:Big Mike\\, again",
+            "DTSTART;TZID=Europe/Oslo:20230805T110000",
+            "DURATION:PT1H30M",
+        ]));
+        assert_eq!(parsed.uid.as_deref(), Some("hike@example.com"));
+
+        let event = parsed.event.expect("Event has problems");
+        assert_eq!(event.summary, "Big Mike, again");
+        assert_eq!(event.start_date, 1691226000);
+        assert_eq!(event.end_date, 1691226000 + 90 * 60);
+        assert!(!event.all_day);
+    }
+
+    #[test]
+    fn parse_event_dates_last_the_whole_day() {
+        let parsed = parse_event(&properties(&[
+            "SUMMARY:Holiday",
+            "DTSTART;VALUE=DATE:20230805",
+        ]));
+        let event = parsed.event.expect("Event has problems");
+        assert_eq!(event.start_date, 1691193600);
+        assert_eq!(event.end_date, 1691193600 + 24 * 60 * 60);
+        assert!(event.all_day);
+    }
+
+    #[test]
+    fn parse_event_reports_problems() {
+        let parsed = parse_event(&properties(&[
+            "DTSTART:20230805T090000Z",
+            "RRULE:FREQ=HOURLY",
+        ]));
+        assert_eq!(
+            parsed.event.unwrap_err(),
+            ["missing SUMMARY", "unsupported RRULE: FREQ=HOURLY"]
+        );
+
+        let parsed = parse_event(&properties(&[
+            "SUMMARY:Backwards",
+            "DTSTART:20230805T090000Z",
+            "DTEND:20230805T080000Z",
+        ]));
+        assert_eq!(parsed.event.unwrap_err(), ["DTEND is before DTSTART"]);
+    }
+
+    #[test]
+    fn escape_escapes_text_syntax() {
+        assert_eq!(escape("a,b;c\\d\r\ne\nf"), "a\\,b\\;c\\\\d\\ne\\nf");
+        assert_eq!(unescape(&escape("a,b;c\\d\ne")), "a,b;c\\d\ne");
+    }
+
+    #[test]
+    fn push_line_folds_long_lines() {
+        let mut calendar = String::new();
+        push_line(&mut calendar, &"a".repeat(80));
+        assert_eq!(
+            calendar,
+            format!("{}\r\n {}\r\n", "a".repeat(75), "a".repeat(5))
+        );
+
+        let mut calendar = String::new();
+        push_line(&mut calendar, "short");
+        assert_eq!(calendar, "short\r\n");
+    }
+
+    #[test]
+    fn push_line_keeps_characters_intact() {
+        let mut calendar = String::new();
+        push_line(&mut calendar, &format!("{}é", "a".repeat(74)));
+        assert_eq!(calendar, format!("{}\r\n é\r\n", "a".repeat(74)));
+    }
+
+    #[test]
+    fn all_day_dates_include_the_last_day() {
+        let midnight = 1691193600;
+        let day = 24 * 60 * 60;
+        let dates = |start, end| {
+            let (start, end) = all_day_dates(start, end).unwrap();
+            format!("{start}-{end}")
+        };
+
+        // DTEND is exclusive so an event ending at midnight doesn't include the next day.
+        assert_eq!(dates(midnight, midnight + day), "20230805-20230806");
+        assert_eq!(dates(midnight, midnight + 2 * day), "20230805-20230807");
+        // Events ending during a day include that whole day, even if they have no length.
+        assert_eq!(
+            dates(midnight + 9 * 3600, midnight + 10 * 3600),
+            "20230805-20230806"
+        );
+        assert_eq!(dates(midnight, midnight), "20230805-20230806");
+    }
+}
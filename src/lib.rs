@@ -1,19 +1,31 @@
+use std::sync::Arc;
+
 use anyhow::Context;
 use axum::{
+    http::{header, HeaderName, Method},
+    middleware,
     routing::{delete, get, post, put, Router},
     Extension,
 };
 use bb8_diesel::DieselConnectionManager;
 use diesel::SqliteConnection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use tower_http::cors::CorsLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+use config::Config;
+
+pub mod config;
 pub mod util;
 
+mod attendee;
+mod dependency;
 mod error;
 mod event;
+mod health;
+mod ics;
+mod recurrence;
 mod schema;
 mod sqlite_mapping;
 mod user;
@@ -27,27 +39,120 @@ pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 #[derive(OpenApi)]
 #[openapi(
     paths(
+        health::get,
         user::get_all,
         user::get_by_username,
         user::post,
+        user::delete_by_username,
+        user::put,
+        user::get_events_csv,
+        user::get_busy_hours,
         event::get_all,
         event::get_by_id,
+        event::get_ics,
         event::post,
         event::delete_by_id,
         event::put,
+        event::put_priority,
+        event::post_publish,
+        event::post_restore,
+        event::get_gaps,
+        event::get_first_slot,
+        event::get_total_time,
+        event::post_after,
+        event::get_neighbors,
+        event::get_conflicts,
+        event::get_extremes,
+        event::get_overlaps,
+        event::post_transfer,
+        event::get_ndjson,
+        event::post_export,
+        event::count,
+        event::get_calendar_feed,
+        event::post_batch_shift,
+        event::get_heatmap,
+        event::get_by_owner,
+        event::get_created_on,
+        event::import_ics,
+        ics::post_validate,
+        attendee::get_all,
+        attendee::post,
+        dependency::get_all,
+        dependency::post,
+        dependency::delete,
     ),
     components(schemas(
+        health::Health,
         user::User,
         user::PostUser,
+        user::PutUser,
+        user::UserWithEventCount,
+        user::BusyHours,
         event::Event,
         event::PostEvent,
-        event::PutEvent
+        event::PutEvent,
+        event::FullCalendarEvent,
+        event::EventFormat,
+        event::EventDetailFormat,
+        event::EventSort,
+        event::EventWeekday,
+        event::EventQuery,
+        event::ExportFormat,
+        event::PutEventPriority,
+        event::EventGap,
+        event::TotalTime,
+        event::EventCount,
+        event::PostEventAfter,
+        event::EventNeighbors,
+        event::EventWithDuration,
+        event::EventExtremes,
+        event::EventWithOverlaps,
+        event::PostEventTransfer,
+        event::EventShift,
+        event::ShiftedEvents,
+        event::HeatmapBucket,
+        event::Heatmap,
+        event::OwnerCount,
+        ics::IcsEvent,
+        ics::IcsEventReport,
+        ics::IcsValidation,
+        event::ImportedEvents,
+        attendee::Attendee,
+        attendee::AttendeeStatus,
+        attendee::PostAttendee,
+        dependency::PostEventDependency,
+        dependency::EventDependencies,
     ))
 )]
 struct ApiDoc;
 
+// Browsers only let front ends on other origins call the API when it answers with the right CORS
+// headers, including for the preflight requests sent before PUT and DELETE requests.
+fn cors_layer(config: &Config) -> CorsLayer {
+    // Only debug builds can start without any configured origins.
+    if config.cors_origins.is_empty() {
+        return CorsLayer::permissive();
+    }
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(config.cors_origins.iter().cloned()))
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::IF_MATCH,
+            header::IF_NONE_MATCH,
+        ])
+        .expose_headers([
+            header::ETAG,
+            HeaderName::from_static("x-result-truncated"),
+            HeaderName::from_static("x-total-count"),
+        ])
+}
+
 // This is where all of the routing happens.
-pub async fn api_route(pool: SqlitePool) -> anyhow::Result<Router> {
+pub async fn api_route(pool: SqlitePool, config: Config) -> anyhow::Result<Router> {
+    let cors = cors_layer(&config);
+
     Ok(Router::new()
         // SwaggerUi will create its paths under /swagger.
         // The ApiDoc::openapi() function was generated by the derive on ApiDoc.
@@ -55,16 +160,54 @@ pub async fn api_route(pool: SqlitePool) -> anyhow::Result<Router> {
         // Routes defined by this application, first we have the path, then the function which
         // handles requests for that path wrapped by a function with the name of the http method
         // that should be listened for.
+        .route("/health", get(health::get))
         .route("/api/user", get(user::get_all))
         .route("/api/user/:username", get(user::get_by_username))
+        .route("/api/user/:username", delete(user::delete_by_username))
+        .route("/api/user/:username", put(user::put))
+        .route("/api/user/:username/events.csv", get(user::get_events_csv))
+        .route("/api/user/:username/busy-hours", get(user::get_busy_hours))
         .route("/api/user", post(user::post))
+        .route("/api/calendar.ics", get(event::get_calendar_feed))
         .route("/api/event", get(event::get_all))
         .route("/api/event", post(event::post))
+        .route("/api/event/gaps", get(event::get_gaps))
+        .route("/api/event/first-slot", get(event::get_first_slot))
+        .route("/api/event/total-time", get(event::get_total_time))
+        .route("/api/event/extremes", get(event::get_extremes))
+        .route("/api/event/overlaps", get(event::get_overlaps))
+        .route("/api/event/ndjson", get(event::get_ndjson))
+        .route("/api/event/export", post(event::post_export))
+        .route("/api/event/count", get(event::count))
+        .route("/api/event/batch/shift", post(event::post_batch_shift))
+        .route("/api/event/heatmap", get(event::get_heatmap))
+        .route("/api/event/by-owner", get(event::get_by_owner))
+        .route("/api/event/created-on", get(event::get_created_on))
+        .route("/api/event/ics/validate", post(ics::post_validate))
+        .route("/api/event/import", post(event::import_ics))
         .route("/api/event/:id", get(event::get_by_id))
         .route("/api/event/:id", delete(event::delete_by_id))
         .route("/api/event/:id", put(event::put))
+        .route("/api/event/:id/priority", put(event::put_priority))
+        .route("/api/event/:id/publish", post(event::post_publish))
+        .route("/api/event/:id/restore", post(event::post_restore))
+        .route("/api/event/:id/ics", get(event::get_ics))
+        .route("/api/event/:id/after", post(event::post_after))
+        .route("/api/event/:id/neighbors", get(event::get_neighbors))
+        .route("/api/event/:id/conflicts", get(event::get_conflicts))
+        .route("/api/event/:id/transfer", post(event::post_transfer))
+        .route("/api/event/:id/attendees", get(attendee::get_all))
+        .route("/api/event/:id/attendees", post(attendee::post))
+        .route("/api/event/:id/dependencies", get(dependency::get_all))
+        .route("/api/event/:id/dependencies", post(dependency::post))
+        .route(
+            "/api/event/:id/dependencies/:depends_on",
+            delete(dependency::delete),
+        )
+        .layer(middleware::from_fn(error::track_request_path))
         .layer(Extension(pool))
-        .layer(CorsLayer::permissive()))
+        .layer(Extension(Arc::new(config)))
+        .layer(cors))
 }
 
 // This just renames the type to make it shorter to type.
@@ -87,3 +230,69 @@ pub async fn setup_database(database_url: String) -> anyhow::Result<SqlitePool>
 
     Ok(pool)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use ts_rs::TS;
+
+    use super::*;
+
+    // Writes `dist/index.ts` re-exporting every binding, so the front end can import all of them
+    // from the package at once. The bindings are exported here as well since the tests ts-rs
+    // generates for them may run after this one.
+    #[test]
+    fn export_bindings_index() {
+        fn export<T: TS + 'static>(names: &mut Vec<String>) {
+            T::export().expect("Failed to export binding");
+            names.push(T::name());
+        }
+
+        let mut names = Vec::new();
+        export::<attendee::Attendee>(&mut names);
+        export::<attendee::AttendeeStatus>(&mut names);
+        export::<attendee::PostAttendee>(&mut names);
+        export::<dependency::EventDependencies>(&mut names);
+        export::<dependency::PostEventDependency>(&mut names);
+        export::<event::Event>(&mut names);
+        export::<event::EventCount>(&mut names);
+        export::<event::EventExtremes>(&mut names);
+        export::<event::EventGap>(&mut names);
+        export::<event::EventNeighbors>(&mut names);
+        export::<event::EventShift>(&mut names);
+        export::<event::EventWithDuration>(&mut names);
+        export::<event::EventWithOverlaps>(&mut names);
+        export::<event::FullCalendarEvent>(&mut names);
+        export::<event::Heatmap>(&mut names);
+        export::<event::ImportedEvents>(&mut names);
+        export::<event::OwnerCount>(&mut names);
+        export::<event::PostEvent>(&mut names);
+        export::<event::PostEventAfter>(&mut names);
+        export::<event::PostEventTransfer>(&mut names);
+        export::<event::PutEvent>(&mut names);
+        export::<event::PutEventPriority>(&mut names);
+        export::<event::ShiftedEvents>(&mut names);
+        export::<event::TotalTime>(&mut names);
+        export::<health::Health>(&mut names);
+        export::<ics::IcsEvent>(&mut names);
+        export::<ics::IcsEventReport>(&mut names);
+        export::<ics::IcsValidation>(&mut names);
+        export::<user::BusyHours>(&mut names);
+        export::<user::PostUser>(&mut names);
+        export::<user::PutUser>(&mut names);
+        export::<user::User>(&mut names);
+        export::<user::UserWithEventCount>(&mut names);
+        names.sort();
+
+        let mut index = String::from(
+            "// This file was generated by the export_bindings_index test. Do not edit this file manually.\n",
+        );
+        for name in names {
+            index.push_str(&format!("export * from \"./{name}\";\n"));
+        }
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("dist/index.ts");
+        std::fs::write(path, index).expect("Failed to write dist/index.ts");
+    }
+}
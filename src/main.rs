@@ -1,7 +1,7 @@
 use anyhow::Context;
 use tracing::*;
 
-use calendar::{api_route, setup_database};
+use calendar::{api_route, config::Config, setup_database};
 
 use std::net::SocketAddr;
 
@@ -27,6 +27,7 @@ async fn main() {
 async fn run() -> anyhow::Result<()> {
     let db_path = std::env::var("DATABASE_URL").context("DATABASE_URL not set")?;
     let db = setup_database(db_path).await?;
+    let config = Config::from_env()?;
 
     let bind_addr: SocketAddr = std::env::var("BIND_ADDRESS")
         .context("BIND_ADDRESS not set")?
@@ -36,7 +37,7 @@ async fn run() -> anyhow::Result<()> {
     info!("Listening on {}", bind_addr);
     info!("Swagger can be found at {}/swagger/", bind_addr);
     axum::Server::try_bind(&bind_addr)?
-        .serve(api_route(db).await?.into_make_service())
+        .serve(api_route(db, config).await?.into_make_service())
         .await
         .unwrap();
 
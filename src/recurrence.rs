@@ -0,0 +1,337 @@
+// Recurring events store an RFC 5545 RRULE like `FREQ=WEEKLY;COUNT=52` and are only expanded into
+// their occurrences when they are read. Only the FREQ, INTERVAL, COUNT and UNTIL parts are
+// supported.
+// https://www.rfc-editor.org/rfc/rfc5545#section-3.3.10
+use std::str::FromStr;
+
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime};
+
+use crate::error::Error;
+use crate::ics;
+
+/// No event is expanded into more occurrences than this in a single request.
+pub const MAX_OCCURRENCES: usize = 1000;
+
+const DAY_SECONDS: i64 = 24 * 60 * 60;
+
+// Repetitions landing on dates which don't exist are skipped, but a real date always follows within
+// a few repetitions. Running into this many in a row means we left the range of supported dates.
+const MAX_SKIPPED_IN_A_ROW: u32 = 100;
+
+// Some rules have to be walked from their first repetition to count them, this keeps that walk
+// short no matter how far away the window is.
+const MAX_COUNT: u32 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<i64>,
+}
+
+impl FromStr for Recurrence {
+    type Err = Error;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidRecurrence(rule.to_string());
+        let rule_body = rule.trim();
+        let rule_body = rule_body.strip_prefix("RRULE:").unwrap_or(rule_body);
+
+        let mut frequency = None;
+        let mut interval = 1;
+        let mut count = None;
+        let mut until = None;
+        for part in rule_body.split(';').filter(|part| !part.is_empty()) {
+            let (name, value) = part.split_once('=').ok_or_else(invalid)?;
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(invalid()),
+                    })
+                }
+                "INTERVAL" => interval = value.parse().map_err(|_| invalid())?,
+                "COUNT" => count = Some(value.parse().map_err(|_| invalid())?),
+                "UNTIL" => until = Some(parse_until(value).ok_or_else(invalid)?),
+                _ => return Err(invalid()),
+            }
+        }
+
+        // COUNT and UNTIL are mutually exclusive according to the RFC.
+        if interval == 0
+            || (count.is_some() && until.is_some())
+            || matches!(count, Some(count) if count > MAX_COUNT)
+        {
+            return Err(invalid());
+        }
+
+        Ok(Recurrence {
+            frequency: frequency.ok_or_else(invalid)?,
+            interval,
+            count,
+            until,
+        })
+    }
+}
+
+// UNTIL is either a date, meaning the whole day is included, or a UTC date-time.
+fn parse_until(value: &str) -> Option<i64> {
+    if let Ok(date) = Date::parse(value, ics::DATE) {
+        return Some(date.next_day()?.midnight().assume_utc().unix_timestamp() - 1);
+    }
+
+    PrimitiveDateTime::parse(value, ics::UTC_DATE_TIME)
+        .ok()
+        .map(|date_time| date_time.assume_utc().unix_timestamp())
+}
+
+// Moves a date by whole months keeping the day of the month, dates which don't exist like the 31st
+// of April are skipped by returning `None`.
+fn add_months(start: OffsetDateTime, months: i64) -> Option<i64> {
+    let month_index = i64::from(start.year()) * 12 + i64::from(u8::from(start.month())) - 1;
+    let month_index = month_index.checked_add(months)?;
+    let year = i32::try_from(month_index.div_euclid(12)).ok()?;
+    let month = Month::try_from(u8::try_from(month_index.rem_euclid(12) + 1).ok()?).ok()?;
+    let date = Date::from_calendar_date(year, month, start.day()).ok()?;
+
+    Some(
+        PrimitiveDateTime::new(date, start.time())
+            .assume_utc()
+            .unix_timestamp(),
+    )
+}
+
+impl Recurrence {
+    // The number of whole months between two repetitions, `None` for daily and weekly rules.
+    fn months(&self) -> Option<i64> {
+        match self.frequency {
+            Frequency::Daily | Frequency::Weekly => None,
+            Frequency::Monthly => Some(i64::from(self.interval)),
+            Frequency::Yearly => Some(i64::from(self.interval) * 12),
+        }
+    }
+
+    // The index of a repetition starting at or before `target`, as late as we can cheaply tell, so
+    // the repetitions before it don't have to be walked.
+    fn first_candidate(&self, start: OffsetDateTime, target: i64) -> i64 {
+        match self.months() {
+            None => {
+                let step = self.nth_start(start, 1).unwrap_or(start.unix_timestamp())
+                    - start.unix_timestamp();
+                ((target - start.unix_timestamp()) / step.max(1)).max(0)
+            }
+            Some(months) => {
+                let Ok(target) = OffsetDateTime::from_unix_timestamp(target) else {
+                    return 0;
+                };
+                let month_index = |date: OffsetDateTime| {
+                    i64::from(date.year()) * 12 + i64::from(u8::from(date.month()))
+                };
+                // One repetition less since the day and time within the month may come after the
+                // target.
+                ((month_index(target) - month_index(start)) / months - 1).max(0)
+            }
+        }
+    }
+
+    // Whether some repetitions land on dates which don't exist. Those don't count towards COUNT, so
+    // counting the repetitions before a window means walking all of them.
+    fn skips_dates(&self, start: OffsetDateTime) -> bool {
+        self.months().is_some() && start.day() > 28
+    }
+
+    // The start of the nth repetition, `None` if that date doesn't exist.
+    fn nth_start(&self, start: OffsetDateTime, n: i64) -> Option<i64> {
+        let steps = n.checked_mul(i64::from(self.interval))?;
+        match self.frequency {
+            Frequency::Daily => Some(start.unix_timestamp() + steps * DAY_SECONDS),
+            Frequency::Weekly => Some(start.unix_timestamp() + steps * 7 * DAY_SECONDS),
+            Frequency::Monthly => add_months(start, steps),
+            Frequency::Yearly => add_months(start, steps.checked_mul(12)?),
+        }
+    }
+
+    /// Returns the start dates of the occurrences of an event starting at `start` and lasting
+    /// `duration` seconds which overlap the window between `from` and `to`, at most `limit`.
+    pub fn occurrences(
+        &self,
+        start: i64,
+        duration: i64,
+        from: i64,
+        to: i64,
+        limit: usize,
+    ) -> Vec<i64> {
+        let Ok(start_date_time) = OffsetDateTime::from_unix_timestamp(start) else {
+            return Vec::new();
+        };
+
+        // We skip straight to the first repetition which could overlap the window. When every
+        // repetition lands on a date which exists, the skipped ones are exactly the ones counted
+        // towards COUNT. Otherwise rules with COUNT have to be walked from the start, which
+        // `MAX_COUNT` keeps short.
+        let mut n = if self.count.is_some() && self.skips_dates(start_date_time) {
+            0
+        } else {
+            self.first_candidate(start_date_time, from - duration)
+        };
+
+        let mut occurrences = Vec::new();
+        let mut counted = u32::try_from(n).unwrap_or(u32::MAX);
+        let mut skipped_in_a_row = 0;
+        while occurrences.len() < limit {
+            if matches!(self.count, Some(count) if counted >= count) {
+                break;
+            }
+
+            let Some(occurrence) = self.nth_start(start_date_time, n) else {
+                // Only monthly and yearly repetitions can land on dates which don't exist, those
+                // are skipped and don't count towards COUNT.
+                skipped_in_a_row += 1;
+                if skipped_in_a_row >= MAX_SKIPPED_IN_A_ROW {
+                    break;
+                }
+                n += 1;
+                continue;
+            };
+            skipped_in_a_row = 0;
+            n += 1;
+            counted += 1;
+
+            if occurrence >= to || matches!(self.until, Some(until) if occurrence > until) {
+                break;
+            }
+
+            if occurrence + duration > from {
+                occurrences.push(occurrence);
+            }
+        }
+
+        occurrences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: i64 = DAY_SECONDS;
+    const HOUR: i64 = 60 * 60;
+    // 2023-08-05 09:00 UTC
+    const START: i64 = 1691226000;
+
+    fn rule(rule: &str) -> Recurrence {
+        rule.parse().expect("Invalid rule")
+    }
+
+    #[test]
+    fn parses_supported_rules() {
+        assert_eq!(
+            rule("FREQ=WEEKLY;COUNT=52"),
+            Recurrence {
+                frequency: Frequency::Weekly,
+                interval: 1,
+                count: Some(52),
+                until: None,
+            }
+        );
+        assert_eq!(
+            rule("RRULE:freq=daily;interval=2;until=20230806"),
+            Recurrence {
+                frequency: Frequency::Daily,
+                interval: 2,
+                count: None,
+                until: Some(1691366400 - 1),
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_rules() {
+        for invalid in [
+            "",
+            "COUNT=3",
+            "FREQ=HOURLY",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;BYDAY=MO",
+            "FREQ=DAILY;COUNT=2;UNTIL=20230901",
+            "FREQ=DAILY;COUNT=10001",
+        ] {
+            assert!(invalid.parse::<Recurrence>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn daily_occurrences_stop_after_count() {
+        let occurrences = rule("FREQ=DAILY;COUNT=3").occurrences(START, HOUR, 0, i64::MAX, 100);
+        assert_eq!(occurrences, [START, START + DAY, START + 2 * DAY]);
+    }
+
+    #[test]
+    fn occurrences_overlapping_the_window_are_returned() {
+        // The second occurrence is still going on when the window starts.
+        let from = START + DAY + HOUR / 2;
+        let occurrences = rule("FREQ=DAILY").occurrences(START, HOUR, from, from + 2 * DAY, 100);
+        assert_eq!(occurrences, [START + DAY, START + 2 * DAY, START + 3 * DAY]);
+
+        let occurrences = rule("FREQ=DAILY").occurrences(START, HOUR, from, from + 2 * DAY, 2);
+        assert_eq!(occurrences, [START + DAY, START + 2 * DAY]);
+    }
+
+    #[test]
+    fn until_date_includes_the_whole_day() {
+        let occurrences =
+            rule("FREQ=DAILY;UNTIL=20230806").occurrences(START, HOUR, 0, i64::MAX, 100);
+        assert_eq!(occurrences, [START, START + DAY]);
+    }
+
+    #[test]
+    fn monthly_occurrences_skip_dates_which_dont_exist() {
+        // 2023-01-31 10:00 UTC, February, April and June have no 31st and don't count.
+        let occurrences =
+            rule("FREQ=MONTHLY;COUNT=4").occurrences(1675159200, HOUR, 0, i64::MAX, 100);
+        assert_eq!(
+            occurrences,
+            [1675159200, 1680256800, 1685527200, 1690797600]
+        );
+    }
+
+    #[test]
+    fn far_windows_skip_ahead_to_the_right_occurrence() {
+        // 2023-01-31 10:00 UTC, the window is the day before 2030-01-31 10:00 UTC.
+        let occurrences =
+            rule("FREQ=MONTHLY").occurrences(1675159200, HOUR, 1896084000 - DAY, 1896134400, 100);
+        assert_eq!(occurrences, [1896084000]);
+
+        let occurrences =
+            rule("FREQ=DAILY;COUNT=10").occurrences(START, HOUR, START + 8 * DAY, i64::MAX, 100);
+        assert_eq!(occurrences, [START + 8 * DAY, START + 9 * DAY]);
+    }
+
+    #[test]
+    fn skipped_repetitions_count_towards_count() {
+        // 2023-01-15 10:00 UTC, the 21st occurrence is on 2024-09-15 10:00 UTC.
+        let start = 1673776800;
+        let twenty_first = 1726394400;
+        let window = (twenty_first - HOUR, twenty_first + HOUR);
+
+        let occurrences =
+            rule("FREQ=MONTHLY;COUNT=30").occurrences(start, HOUR, window.0, window.1, 100);
+        assert_eq!(occurrences, [twenty_first]);
+
+        let occurrences =
+            rule("FREQ=MONTHLY;COUNT=20").occurrences(start, HOUR, window.0, window.1, 100);
+        assert!(occurrences.is_empty());
+    }
+}
@@ -1,5 +1,24 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    use crate::sqlite_mapping::*;
+
+    event_attendees (event_id, username) {
+        event_id -> Integer,
+        username -> Text,
+        status -> Text,
+    }
+}
+
+diesel::table! {
+    use crate::sqlite_mapping::*;
+
+    event_dependencies (event_id, depends_on_event_id) {
+        event_id -> Integer,
+        depends_on_event_id -> Integer,
+    }
+}
+
 diesel::table! {
     use crate::sqlite_mapping::*;
 
@@ -10,11 +29,19 @@ diesel::table! {
         color -> Text,
         start_date -> Integer,
         end_date -> Integer,
-        location_lng -> Nullable<Float>,
-        location_lat -> Nullable<Float>,
+        location_lng -> Nullable<Double>,
+        location_lat -> Nullable<Double>,
         location_name -> Nullable<Text>,
         created_at -> Integer,
         edited_at -> Nullable<Integer>,
+        priority -> Integer,
+        version -> Integer,
+        draft -> Bool,
+        recurrence -> Nullable<Text>,
+        owner -> Nullable<Text>,
+        deleted_at -> Nullable<Integer>,
+        all_day -> Bool,
+        timezone -> Nullable<Text>,
     }
 }
 
@@ -24,10 +51,13 @@ diesel::table! {
     users (username) {
         username -> Text,
         created_at -> Integer,
+        deleted_at -> Nullable<Integer>,
     }
 }
 
 diesel::allow_tables_to_appear_in_same_query!(
+    event_attendees,
+    event_dependencies,
     events,
     users,
 );
@@ -1,18 +1,28 @@
-use axum::extract::rejection::JsonRejection;
+use axum::extract::rejection::{JsonRejection, QueryRejection};
 use diesel::prelude::*;
 
 use anyhow::Context;
-use axum::{extract::Path, Extension, Json};
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::{
+    extract::{Path, Query},
+    Extension, Json,
+};
 use diesel::dsl::sql;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
 use diesel::sql_types::Bool;
 use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
 use tracing::debug;
 use ts_rs::TS;
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
-use crate::error::Error;
+use crate::attendee;
+use crate::config::Config;
+use crate::error::{get_conn, Error};
+use crate::event;
 use crate::schema::users;
-use crate::util::unix_timestamp;
+use crate::util::{check_bounded_window, local_datetime, request_timezone, unix_timestamp};
 use crate::SqlitePool;
 
 // `derive` automatically generates code for a type. Here we use the following:
@@ -51,6 +61,37 @@ pub struct User {
     /// A unix timestamp of when this alias was created.
     #[schema(example = 1670802822)]
     pub created_at: i64,
+
+    /// When the user was deleted, deleted users are only returned when explicitly requested.
+    #[schema(example = 1691830900)]
+    pub deleted_at: Option<i64>,
+}
+
+/// A user together with how many published events they own.
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/", rename_all = "camelCase")]
+#[serde(rename_all = "camelCase")]
+pub struct UserWithEventCount {
+    #[ts(flatten)]
+    #[serde(flatten)]
+    pub user: User,
+
+    #[schema(example = 12)]
+    pub event_count: i64,
+}
+
+// `IntoParams` does for query parameters what `ToSchema` does for bodies.
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct UserQuery {
+    /// Return every user as `UserWithEventCount` with the number of published events they own.
+    #[param(example = true)]
+    pub include_event_count: Option<bool>,
+
+    /// Also return users who have been deleted.
+    #[param(example = true)]
+    pub include_deleted: Option<bool>,
 }
 
 // Here we use an attribute like macro to provide some information needed by Swagger.
@@ -60,8 +101,9 @@ pub struct User {
     get,
     path = "/api/user",
     responses(
-        (status = 200, description = "Users are returned", body = [User]),
-    )
+        (status = 200, description = "Users are returned, as `UserWithEventCount` when includeEventCount is set", body = [User]),
+    ),
+    params(UserQuery)
 )]
 // The web framework we use (axum) allows us to request various parameters which it can fill in for
 // us. `Extension`s are use for arbitrary state we want to keep, in this case we are storing the
@@ -75,12 +117,20 @@ pub struct User {
 // `src/error.rs`. If everything goes well we return a list of `User`s which is wrapped in a `Json`
 // type to let axum (the web framework) know that it should be serialized with serde. (See the
 // `Serialize` derive on the `User` type.)
-pub async fn get_all(Extension(pool): Extension<SqlitePool>) -> Result<Json<Vec<User>>, Error> {
+pub async fn get_all(
+    Extension(pool): Extension<SqlitePool>,
+    query: Result<Query<UserQuery>, QueryRejection>,
+) -> Result<Response, Error> {
+    // Query parameters which don't parse are turned into our own error by the question mark, the
+    // same way as the JSON bodies of the functions further down.
+    let Query(query) = query?;
+
     // We store a pool of connections to the database, I'm not sure how much sense this makes for
     // sqlite but it does make it simpler to work with it in the context of diesel and axum.
     //
-    // We can simply get a connection from that pool whenever we need one.
-    let mut conn = pool.get().await.expect("can connect to sqlite");
+    // We can simply get a connection from that pool whenever we need one. If none becomes free in
+    // time `get_conn` returns an internal error which the question mark passes on.
+    let mut conn = get_conn(&pool).await?;
 
     // This simply logs to the console, there are a few of these for different log levels but they
     // have to be `use`d from tracing (e.g. use tracing::debug).
@@ -92,15 +142,24 @@ pub async fn get_all(Extension(pool): Extension<SqlitePool>) -> Result<Json<Vec<
     // it is to have clashing variables with the fields that puts into scope. While there may be a
     // better way for now you can find `use crate::schema::users;` at the top of the file and we
     // access the table via `users::dsl::users` instead of simply `users`.
-    let users = users::dsl::users
+    let mut users_query = users::dsl::users.into_boxed();
+
+    // Deleted users are kept in the table, the query is boxed so this filter can be left out when
+    // they are asked for.
+    // https://docs.rs/diesel/latest/diesel/query_dsl/trait.QueryDsl.html#method.into_boxed
+    if !query.include_deleted.unwrap_or(false) {
+        users_query = users_query.filter(users::dsl::deleted_at.is_null());
+    }
+
+    let users: Vec<User> = users_query
         // This is where the data is actually loaded which is why we have to pass in the
         // connection, I have not bothered to look into why we have to do the weird `&mut *` dance
         // yet but it's probably because the type doesn't match exactly and is converted by doing
         // that.
         // The function knows what the resulting type should be because the compiler looks at the
-        // return type of `get_all` and infers which type `users` needs to have. If it was to fail
-        // we could help it with the good old turbofish like this `load::<User>(...)`. `load`
-        // always returns a Vec (growable array) of the type that its supposed to load.
+        // type we gave `users` above. Instead we could also help it with the good old turbofish
+        // like this `load::<User>(...)`. `load` always returns a Vec (growable array) of the type
+        // that its supposed to load.
         //
         // Alternatives to load can be found here:
         // https://docs.rs/diesel/latest/diesel/prelude/trait.RunQueryDsl.html
@@ -114,9 +173,27 @@ pub async fn get_all(Extension(pool): Extension<SqlitePool>) -> Result<Json<Vec<
     // When logging we can also provide additional values we want to log.
     debug!(count = users.len(), "Returning users");
 
+    // The events of all users are counted with a single query instead of one per user.
+    if query.include_event_count.unwrap_or(false) {
+        let counts: HashMap<String, i64> = event::count_by_owner(&mut conn, None)?
+            .into_iter()
+            .filter_map(|(owner, count)| Some((owner?, count)))
+            .collect();
+        let users: Vec<UserWithEventCount> = users
+            .into_iter()
+            .map(|user| UserWithEventCount {
+                event_count: counts.get(&user.username).copied().unwrap_or(0),
+                user,
+            })
+            .collect();
+
+        return Ok(Json(users).into_response());
+    }
+
     // As mentioned above wrapping the data in the `Json` type informs axum that we would like it
-    // serialized into json via the serde library.
-    Ok(Json(users))
+    // serialized into json via the serde library. Since we return two different types depending on
+    // the query the `Json` is turned into a plain `Response` right away.
+    Ok(Json(users).into_response())
 }
 
 // See the `get_all` function right above.
@@ -125,11 +202,12 @@ pub async fn get_all(Extension(pool): Extension<SqlitePool>) -> Result<Json<Vec<
     get,
     path = "/api/user/{username}",
     responses(
-        (status = 200, description = "User data is returned", body = User),
+        (status = 200, description = "User data is returned, as `UserWithEventCount` when includeEventCount is set", body = User),
         (status = 404, description = "User does not exist"),
     ),
     params(
         ("username" = String, Path, description = "Username of the user to query"),
+        UserQuery,
     )
 )]
 // See `get_all` for more information but here we have an example of another parameter.
@@ -138,16 +216,24 @@ pub async fn get_all(Extension(pool): Extension<SqlitePool>) -> Result<Json<Vec<
 pub async fn get_by_username(
     Path(username): Path<String>,
     Extension(pool): Extension<SqlitePool>,
-) -> Result<Json<User>, Error> {
+    query: Result<Query<UserQuery>, QueryRejection>,
+) -> Result<Response, Error> {
+    let Query(query) = query?;
+
     // See `get_all`.
-    let mut conn = pool.get().await.expect("can connect to sqlite");
+    let mut conn = get_conn(&pool).await?;
 
     // If we don't provide a name to variables listed in logs it will fall back to the name of the
     // variable.
     debug!(username, "Trying to find user by name");
 
     // Same as in `get_all` but we also filter and only return one value.
-    let user = users::dsl::users
+    let mut user_query = users::dsl::users.into_boxed();
+    if !query.include_deleted.unwrap_or(false) {
+        user_query = user_query.filter(users::dsl::deleted_at.is_null());
+    }
+
+    let user = user_query
         // Once again this looks slightly different than the example in the diesel guide because I
         // don't like how they pull everything into scope with their `use` statement.
         //
@@ -169,7 +255,129 @@ pub async fn get_by_username(
 
     debug!(?user, "Found user");
 
-    Ok(Json(user))
+    if query.include_event_count.unwrap_or(false) {
+        let event_count = event::count_owned(&mut conn, &user.username)?;
+        return Ok(Json(UserWithEventCount { user, event_count }).into_response());
+    }
+
+    Ok(Json(user).into_response())
+}
+
+// See the `get_all` function at the top of the file.
+/// Export the events of a user as CSV.
+#[utoipa::path(
+    get,
+    path = "/api/user/{username}/events.csv",
+    responses(
+        (status = 200, description = "The published events owned by the user, only the header row if they have none", body = String, content_type = "text/csv"),
+        (status = 404, description = "User does not exist"),
+    ),
+    params(
+        ("username" = String, Path, description = "Username of the user whose events to export"),
+    )
+)]
+pub async fn get_events_csv(
+    Path(username): Path<String>,
+    Extension(pool): Extension<SqlitePool>,
+) -> Result<impl IntoResponse, Error> {
+    let mut conn = get_conn(&pool).await?;
+    debug!(username, "Exporting events of user");
+
+    let user = users::dsl::users
+        .filter(users::dsl::username.eq(username))
+        .filter(users::dsl::deleted_at.is_null())
+        .first::<User>(&mut *conn)
+        .optional()
+        .context("Failed to query user")?
+        .ok_or(Error::NotFound)?;
+
+    // The CSV has the same columns as the one of `POST /api/event/export`.
+    let events = event::load_owned(&mut conn, &user.username)?;
+    debug!(count = events.len(), "Returning events of user");
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"events.csv\"",
+            ),
+        ],
+        event::write_csv(&events),
+    ))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BusyHoursQuery {
+    /// Unix timestamp of the start of the window.
+    #[param(example = 1690848000)]
+    pub from: i64,
+
+    /// Unix timestamp of the end of the window.
+    #[param(example = 1693526400)]
+    pub to: i64,
+
+    /// IANA timezone the hours are in, defaults to DEFAULT_TIMEZONE or UTC.
+    #[param(example = "Europe/Oslo")]
+    pub tz: Option<String>,
+}
+
+/// How many events of a user start in every hour of the day.
+#[derive(Debug, Serialize, TS, ToSchema)]
+#[ts(export, export_to = "dist/")]
+pub struct BusyHours {
+    /// Indexed by the hour of the day, from 0 to 23.
+    #[schema(example = json!([0, 0, 0, 0, 0, 0, 0, 0, 2, 5, 3, 1, 0, 1, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0]))]
+    pub counts: Vec<u32>,
+}
+
+// See the `get_all` function at the top of the file.
+/// Get the hours of the day in which a user's events start.
+#[utoipa::path(
+    get,
+    path = "/api/user/{username}/busy-hours",
+    responses(
+        (status = 200, description = "Event counts per hour are returned", body = BusyHours),
+        (status = 400, description = "The window or timezone is invalid"),
+        (status = 404, description = "User does not exist"),
+    ),
+    params(
+        ("username" = String, Path, description = "Username of the user to query"),
+        BusyHoursQuery,
+    )
+)]
+pub async fn get_busy_hours(
+    Path(username): Path<String>,
+    Extension(pool): Extension<SqlitePool>,
+    Extension(config): Extension<Arc<Config>>,
+    query: Result<Query<BusyHoursQuery>, QueryRejection>,
+) -> Result<Json<BusyHours>, Error> {
+    let Query(query) = query?;
+    check_bounded_window(&config, query.from, query.to)?;
+    let tz = request_timezone(&config, query.tz.as_deref())?;
+
+    let mut conn = get_conn(&pool).await?;
+    debug!(username, ?query, "Counting busy hours of user");
+
+    let user = users::dsl::users
+        .filter(users::dsl::username.eq(username))
+        .filter(users::dsl::deleted_at.is_null())
+        .first::<User>(&mut *conn)
+        .optional()
+        .context("Failed to query user")?
+        .ok_or(Error::NotFound)?;
+
+    // Recurring events count once for every occurrence in the window.
+    let events = event::load_window(&mut conn, query.from, query.to, Some(&user.username))?;
+    let mut counts = vec![0; 24];
+    for event in events {
+        if let Some(date) = local_datetime(event.start_date, tz) {
+            counts[usize::from(date.hour())] += 1;
+        }
+    }
+
+    Ok(Json(BusyHours { counts }))
 }
 
 /// The user object required during creation, the missing fields are generated by the back end.
@@ -194,6 +402,7 @@ pub struct PostUser {
     request_body = PostUser,
     responses(
         (status = 200, description = "The user was successfully created.", body = User),
+        (status = 400, description = "A user with that name already exists."),
     )
 )]
 pub async fn post(
@@ -204,76 +413,210 @@ pub async fn post(
     let Json(request) = request?;
 
     // See `get_all`.
-    let mut conn = pool.get().await.expect("can connect to sqlite");
+    let mut conn = get_conn(&pool).await?;
 
     // This check if not neccessary to prevent duplicate database entries because the username is
     // the primary key in the database which means it is unique. It is nice to check for this
     // though since otherwise we get a diesel error during the insert which is difficult to work
     // with and we would default to turning it into an internal server error.
     //
-    // To ensure that no users are inserted between this check and the actual insertion we should
-    // use a transaction but let's skip that for now.
-    //
-    // Technically this is the same as in `get_by_username` but we don't care about the returned
-    // data. Instead we want to know if any data is returned.
-    let result = users::dsl::users
-        .filter(users::dsl::username.eq(&request.username))
-        // We simply return 1 and tell diesel to treat it as a bool to minimize the amount of data
-        // returned since we won't be using it.
-        .select(sql::<Bool>("1"))
-        .first::<bool>(&mut **conn)
-        .optional()
-        .context("Failed to check for existing users")?;
-
-    // To check what's happening and to make a point let's log the output of that.
-    // Since result is an `Option<bool>` and there is no obvious way to convert it to a `String`
-    // Rust doesn't provide the normal `Display` trait for conversions to `String`s.
-    // Instead we have to use the `Debug` trait which is not intended for users of the application
-    // and creates a `String` that looks quite similar to the Rust type it was created from.
-    // The `tracing` log library let's us use `Debug` for parameters by prefixing them with a
-    // question mark.
-    debug!(
-        ?result,
-        username = request.username,
-        "Checked for existing users with the provided name"
-    );
-
-    // Just as an example this is how you would print using the standard library only:
-    //
-    // Here {} is replaced by the variables and `:?` indicates that we want to use `Debug`
-    // formatting. By adding an additional `#` we can format it across multiple lines too.
-    // Variables can also be used directly within the `{}` since a recent Rust version.
+    // To ensure that no users are inserted between this check and the actual insertion both of
+    // them happen within a transaction. The closure receives the connection to use for it and if
+    // it returns an error everything done inside of it is rolled back.
     //
-    // There are many more options too: https://doc.rust-lang.org/std/fmt/index.html
-    println!(
-        "username: {}, result: {:?}, formatted result: {result:#?}",
-        request.username, result
-    );
-
-    // Very quick way of getting output for debugging, it prints the file, line number and it's
-    // content using `variable = {:#?}` formatting.
-    dbg!(result);
-
-    // Now we can check if data was returned when we looked for the user, if it was then we can't
-    // create another user with that name.
-    if result.is_some() {
-        return Err(Error::UserExists);
-    }
+    // A normal transaction only takes the write lock at the insert, which fails with SQLITE_BUSY
+    // if another connection wrote in the meantime. An immediate transaction takes it right away so
+    // concurrent requests wait for each other instead.
+    // https://docs.rs/diesel/latest/diesel/sqlite/struct.SqliteConnection.html#method.immediate_transaction
+    let user = conn.immediate_transaction::<_, Error, _>(|conn| {
+        // Technically this is the same as in `get_by_username` but we don't care about the
+        // returned data. Instead we want to know if any data is returned.
+        let result = users::dsl::users
+            .filter(users::dsl::username.eq(&request.username))
+            // We simply return 1 and tell diesel to treat it as a bool to minimize the amount of
+            // data returned since we won't be using it.
+            .select(sql::<Bool>("1"))
+            .first::<bool>(conn)
+            .optional()
+            .context("Failed to check for existing users")?;
+
+        // To check what's happening and to make a point let's log the output of that.
+        // Since result is an `Option<bool>` and there is no obvious way to convert it to a
+        // `String` Rust doesn't provide the normal `Display` trait for conversions to `String`s.
+        // Instead we have to use the `Debug` trait which is not intended for users of the
+        // application and creates a `String` that looks quite similar to the Rust type it was
+        // created from. The `tracing` log library let's us use `Debug` for parameters by prefixing
+        // them with a question mark.
+        debug!(
+            ?result,
+            username = request.username,
+            "Checked for existing users with the provided name"
+        );
 
-    // Self explanatory I think, we are just getting the seconds since UNIX_EPOCH.
-    debug!(?request, "Inserting user");
+        // Just as an example this is how you would print using the standard library only:
+        //
+        // Here {} is replaced by the variables and `:?` indicates that we want to use `Debug`
+        // formatting. By adding an additional `#` we can format it across multiple lines too.
+        // Variables can also be used directly within the `{}` since a recent Rust version.
+        //
+        // There are many more options too: https://doc.rust-lang.org/std/fmt/index.html
+        println!(
+            "username: {}, result: {:?}, formatted result: {result:#?}",
+            request.username, result
+        );
 
-    // The actual insertion of the new user into the users table.
-    let user = diesel::insert_into(users::table)
-        // The values passed in here have to implement the `Insertible` trait which is
-        // automatically implemented by the `Insertible` derive.
-        .values(&request)
-        // `execute()` just runs the query without expecting any results so it either returns an
-        // error or nothing.
-        .get_result(&mut *conn)
-        .context("Failed to insert user")?;
+        // Very quick way of getting output for debugging, it prints the file, line number and it's
+        // content using `variable = {:#?}` formatting.
+        dbg!(result);
+
+        // Now we can check if data was returned when we looked for the user, if it was then we
+        // can't create another user with that name.
+        if result.is_some() {
+            return Err(Error::UserExists);
+        }
+
+        // Self explanatory I think, we are just getting the seconds since UNIX_EPOCH.
+        debug!(?request, "Inserting user");
+
+        // The actual insertion of the new user into the users table.
+        let user = diesel::insert_into(users::table)
+            // The values passed in here have to implement the `Insertible` trait which is
+            // automatically implemented by the `Insertible` derive.
+            .values(&request)
+            // `get_result()` runs the query and returns the inserted row thanks to sqlite's
+            // RETURNING clause.
+            .get_result(conn);
+
+        // Should the check above still miss a user, e.g. because another connection inserted it
+        // without a transaction, the database refuses the duplicate primary key. That is the
+        // same error as the one we return above so we don't turn it into an internal error.
+        match user {
+            Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                Err(Error::UserExists)
+            }
+            user => Ok(user.context("Failed to insert user")?),
+        }
+    })?;
 
     debug!("Inserted user successfully");
 
     Ok(Json(user))
 }
+
+// See the `get_all` function at the top of the file.
+/// Delete a user by username.
+#[utoipa::path(
+    delete,
+    path = "/api/user/{username}",
+    responses(
+        (status = 200, description = "The user was marked as deleted, their events and attendances are kept"),
+        (status = 404, description = "User does not exist or was already deleted"),
+    ),
+    params(
+        ("username" = String, Path, description = "Username of the user to delete"),
+    )
+)]
+pub async fn delete_by_username(
+    Path(username): Path<String>,
+    Extension(pool): Extension<SqlitePool>,
+) -> Result<(), Error> {
+    let mut conn = get_conn(&pool).await?;
+    debug!(username, "Deleting user");
+
+    // The user is only marked as deleted so that their events and attendances still point to
+    // them, which also keeps their name taken.
+    // `execute` returns the number of affected rows, if nothing was updated the user didn't exist.
+    let deleted = diesel::update(
+        users::dsl::users
+            .filter(users::dsl::username.eq(&username))
+            .filter(users::dsl::deleted_at.is_null()),
+    )
+    .set(users::dsl::deleted_at.eq(unix_timestamp()))
+    .execute(&mut *conn)
+    .context("Failed to delete user")?;
+
+    if deleted == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+/// The user fields which can be changed, fields which are left out stay the same.
+#[derive(Debug, Deserialize, TS, ToSchema, AsChangeset)]
+#[ts(export, export_to = "dist/")]
+#[serde(rename_all = "camelCase")]
+#[diesel(table_name = users)]
+pub struct PutUser {
+    #[schema(example = "alice")]
+    pub username: Option<String>,
+}
+
+// See the `get_all` function at the top of the file.
+/// Update a user by username.
+#[utoipa::path(
+    put,
+    path = "/api/user/{username}",
+    request_body = PutUser,
+    responses(
+        (status = 200, description = "The user was updated", body = User),
+        (status = 400, description = "A user with the new username already exists"),
+        (status = 404, description = "User does not exist"),
+    ),
+    params(
+        ("username" = String, Path, description = "Username of the user to update"),
+    )
+)]
+pub async fn put(
+    Path(username): Path<String>,
+    Extension(pool): Extension<SqlitePool>,
+    request: Result<Json<PutUser>, JsonRejection>,
+) -> Result<Json<User>, Error> {
+    let Json(request) = request?;
+    let mut conn = get_conn(&pool).await?;
+    debug!(username, ?request, "Updating user");
+
+    // Just like in `post` the check for an existing user and the update happen in an immediate
+    // transaction so no user can take the new name in between.
+    let user = conn.immediate_transaction::<_, Error, _>(|conn| {
+        let user = users::dsl::users
+            .filter(users::dsl::username.eq(&username))
+            .filter(users::dsl::deleted_at.is_null())
+            .first::<User>(conn)
+            .optional()
+            .context("Failed to query user")?
+            .ok_or(Error::NotFound)?;
+
+        // Diesel refuses to run an update without any changes so we return the user as is.
+        let Some(new_username) = &request.username else {
+            return Ok(user);
+        };
+
+        // Usernames are case insensitive so a user changing the case of their own name is not a
+        // conflict with themselves.
+        let taken = users::dsl::users
+            .filter(users::dsl::username.eq(new_username))
+            .filter(users::dsl::username.ne(&username))
+            .select(sql::<Bool>("1"))
+            .first::<bool>(conn)
+            .optional()
+            .context("Failed to check for existing users")?;
+
+        if taken.is_some() {
+            return Err(Error::UserExists);
+        }
+
+        let user = diesel::update(users::dsl::users.filter(users::dsl::username.eq(&username)))
+            .set(&request)
+            .get_result::<User>(conn)
+            .context("Failed to update user")?;
+        event::rename_owner(conn, &username, &user.username)?;
+        attendee::rename_user(conn, &username, &user.username)?;
+
+        Ok(user)
+    })?;
+
+    debug!(?user, "Updated user");
+
+    Ok(Json(user))
+}
@@ -1,7 +1,11 @@
 use std::{ops::Deref, time::SystemTime};
 
+use crate::config::{Config, WordFilter, WordFilterMode};
 use crate::error::Error;
+use anyhow::Context;
 use serde::{Deserialize, Deserializer};
+use time::{format_description, format_description::well_known::Rfc3339, Date, OffsetDateTime};
+use time_tz::{timezones, OffsetDateTimeExt, PrimitiveDateTimeExt, Tz};
 
 // This is what we used for arrays in parameters for hivefriends so we may want to use it again.
 pub fn comma_string<'de, D>(deserializer: D) -> Result<Option<Vec<String>>, D::Error>
@@ -22,6 +26,127 @@ pub fn unix_timestamp() -> i64 {
     SystemTime::UNIX_EPOCH.elapsed().unwrap().as_secs() as i64
 }
 
+// Formats a unix timestamp as an ISO 8601 (RFC 3339) date in UTC, e.g. `2023-08-05T09:00:00Z`.
+pub fn iso_timestamp(timestamp: i64) -> anyhow::Result<String> {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .context("Timestamp is out of range")?
+        .format(&Rfc3339)
+        .context("Failed to format timestamp")
+}
+
+// Looks up an IANA timezone name like `Europe/Oslo`.
+pub fn parse_timezone(name: &str) -> Result<&'static Tz, Error> {
+    timezones::get_by_name(name).ok_or_else(|| Error::InvalidTimezone(name.to_string()))
+}
+
+// The timezone a request asked for, or DEFAULT_TIMEZONE when it didn't ask for one.
+pub fn request_timezone(config: &Config, tz: Option<&str>) -> Result<Option<&'static Tz>, Error> {
+    match tz {
+        Some(name) => parse_timezone(name).map(Some),
+        None => Ok(config.default_timezone()),
+    }
+}
+
+// Converts a unix timestamp into a date in the given timezone, or in UTC if there is none.
+pub fn local_datetime(timestamp: i64, tz: Option<&Tz>) -> Option<OffsetDateTime> {
+    let date = OffsetDateTime::from_unix_timestamp(timestamp).ok()?;
+    Some(match tz {
+        Some(tz) => date.to_timezone(tz),
+        None => date,
+    })
+}
+
+// Parses a calendar day like `2023-08-05`.
+pub fn parse_date(date: &str) -> Result<Date, Error> {
+    let format =
+        format_description::parse("[year]-[month]-[day]").context("Invalid date format")?;
+    Date::parse(date, &format).map_err(|_| Error::InvalidDate(date.to_string()))
+}
+
+// Returns the unix timestamps of the first second of the day and of the day after it, in the given
+// timezone or in UTC if there is none.
+pub fn day_bounds(date: Date, tz: Option<&Tz>) -> Option<(i64, i64)> {
+    let start_of = |date: Date| {
+        let midnight = date.midnight();
+        let start = match tz {
+            // Midnight can be skipped by a DST change in which case there is no such time.
+            Some(tz) => midnight.assume_timezone(tz).take_first(),
+            None => Some(midnight.assume_utc()),
+        };
+        start.map(OffsetDateTime::unix_timestamp)
+    };
+
+    Some((start_of(date)?, start_of(date.next_day()?)?))
+}
+
+// Coordinates are stored with the configured number of decimals, 6 by default which is roughly
+// 10cm of precision. Anything past that is float noise which would make otherwise equal
+// coordinates compare as different.
+pub fn round_coordinate(coordinate: f64, decimals: u32) -> f64 {
+    let factor = 10f64.powi(decimals as i32);
+    (coordinate * factor).round() / factor
+}
+
+// Colors are stored as CSS hex colors, either in the short `#rgb` or the long `#rrggbb` form.
+pub fn check_hex_color(color: Option<&str>) -> Result<(), Error> {
+    if let Some(color) = color {
+        let valid = match color.strip_prefix('#') {
+            Some(digits) => {
+                matches!(digits.len(), 3 | 6) && digits.chars().all(|c| c.is_ascii_hexdigit())
+            }
+            None => false,
+        };
+
+        if !valid {
+            return Err(Error::InvalidColor(color.to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+// Colors are compared case insensitively so `#87D45D` and `#87d45d` are the same color.
+pub fn normalize_color(color: &str) -> String {
+    color.trim().to_lowercase()
+}
+
+// When a palette is configured only the colors in it may be used.
+pub fn check_palette(color: Option<&str>, palette: Option<&[String]>) -> Result<(), Error> {
+    if let (Some(color), Some(palette)) = (color, palette) {
+        if !palette.contains(&normalize_color(color)) {
+            return Err(Error::ColorNotInPalette(color.to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+// A location is either a complete coordinate pair within the valid ranges or no coordinates at all.
+pub fn validate_coordinates(lat: Option<f64>, lng: Option<f64>) -> Result<(), Error> {
+    let (lat, lng) = match (lat, lng) {
+        (Some(lat), Some(lng)) => (lat, lng),
+        (None, None) => return Ok(()),
+        _ => return Err(Error::IncompleteCoordinates),
+    };
+
+    if !(-90.0..=90.0).contains(&lat) {
+        return Err(Error::CoordinateOutOfRange {
+            field: "location_lat",
+            minimum: -90.0,
+            maximum: 90.0,
+        });
+    }
+    if !(-180.0..=180.0).contains(&lng) {
+        return Err(Error::CoordinateOutOfRange {
+            field: "location_lng",
+            minimum: -180.0,
+            maximum: 180.0,
+        });
+    }
+
+    Ok(())
+}
+
 // This is part of what we used to do input validation for hivefriends.
 pub fn check_length(
     field_name: &'static str,
@@ -40,3 +165,177 @@ pub fn check_length(
 
     Ok(())
 }
+
+// Validates the `from` and `to` query parameters of endpoints that work on a window of time.
+pub fn check_window(from: i64, to: i64) -> Result<(), Error> {
+    if from > to {
+        return Err(Error::InvalidWindow);
+    }
+
+    Ok(())
+}
+
+// Endpoints which do work for every hour or day of a window, or expand recurring events within it,
+// also limit how long it can be so a single request can't keep the server busy.
+pub fn check_bounded_window(config: &Config, from: i64, to: i64) -> Result<(), Error> {
+    check_window(from, to)?;
+
+    let maximum = config.max_window_seconds;
+    if to.saturating_sub(from) > maximum {
+        return Err(Error::WindowTooLong { maximum });
+    }
+
+    Ok(())
+}
+
+// Checks a field against the configured word filter. Depending on the mode the field is either
+// rejected or its blocked words are replaced by asterisks in place.
+//
+// Only whole words are matched so that blocking a word doesn't affect longer words containing it.
+pub fn filter_words(
+    field_name: &'static str,
+    field: Option<&mut String>,
+    word_filter: Option<&WordFilter>,
+) -> Result<(), Error> {
+    let (Some(field), Some(word_filter)) = (field, word_filter) else {
+        return Ok(());
+    };
+
+    let mut filtered = String::with_capacity(field.len());
+    let mut found = false;
+    let mut rest = field.as_str();
+    while let Some(first) = rest.chars().next() {
+        // Split the text into alternating runs of word and non-word characters.
+        let is_word = first.is_alphanumeric();
+        let end = rest
+            .find(|c: char| c.is_alphanumeric() != is_word)
+            .unwrap_or(rest.len());
+        let (run, remaining) = rest.split_at(end);
+        rest = remaining;
+
+        if is_word && word_filter.blocked_words.contains(&run.to_lowercase()) {
+            found = true;
+            filtered.extend(run.chars().map(|_| '*'));
+        } else {
+            filtered.push_str(run);
+        }
+    }
+
+    if found {
+        match word_filter.mode {
+            WordFilterMode::Reject => return Err(Error::BlockedWord(field_name)),
+            WordFilterMode::Mask => *field = filtered,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_window_rejects_windows_ending_before_they_start() {
+        assert!(check_window(100, 200).is_ok());
+        assert!(check_window(100, 100).is_ok());
+        assert!(matches!(check_window(200, 100), Err(Error::InvalidWindow)));
+    }
+
+    fn word_filter(mode: WordFilterMode) -> WordFilter {
+        WordFilter {
+            blocked_words: vec!["darn".to_string()],
+            mode,
+        }
+    }
+
+    #[test]
+    fn filter_words_masks_whole_words() {
+        let filter = word_filter(WordFilterMode::Mask);
+        let mut title = "Darn, darnation! darn".to_string();
+        filter_words("title", Some(&mut title), Some(&filter)).unwrap();
+        assert_eq!(title, "****, darnation! ****");
+    }
+
+    #[test]
+    fn filter_words_rejects_blocked_words() {
+        let filter = word_filter(WordFilterMode::Reject);
+        let mut title = "What a darn day".to_string();
+        assert!(matches!(
+            filter_words("title", Some(&mut title), Some(&filter)),
+            Err(Error::BlockedWord("title"))
+        ));
+
+        let mut title = "Darnation".to_string();
+        assert!(filter_words("title", Some(&mut title), Some(&filter)).is_ok());
+        assert!(filter_words("title", None, Some(&filter)).is_ok());
+    }
+
+    #[test]
+    fn validate_coordinates_checks_pairs_and_ranges() {
+        assert!(validate_coordinates(Some(60.052), Some(7.4142)).is_ok());
+        assert!(validate_coordinates(Some(-90.0), Some(180.0)).is_ok());
+        assert!(validate_coordinates(None, None).is_ok());
+        assert!(matches!(
+            validate_coordinates(Some(60.052), None),
+            Err(Error::IncompleteCoordinates)
+        ));
+        assert!(matches!(
+            validate_coordinates(None, Some(7.4142)),
+            Err(Error::IncompleteCoordinates)
+        ));
+        assert!(matches!(
+            validate_coordinates(Some(90.5), Some(7.4142)),
+            Err(Error::CoordinateOutOfRange {
+                field: "location_lat",
+                ..
+            })
+        ));
+        assert!(matches!(
+            validate_coordinates(Some(60.052), Some(-180.5)),
+            Err(Error::CoordinateOutOfRange {
+                field: "location_lng",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn normalize_color_ignores_case_and_whitespace() {
+        assert_eq!(normalize_color(" #87D45D "), "#87d45d");
+        assert_eq!(normalize_color("#87d45d"), "#87d45d");
+    }
+
+    #[test]
+    fn check_palette_compares_normalized_colors() {
+        let palette = ["#87d45d".to_string()];
+        assert!(check_palette(Some("#87D45D"), Some(&palette[..])).is_ok());
+        assert!(check_palette(None, Some(&palette[..])).is_ok());
+        assert!(check_palette(Some("#ff0000"), None).is_ok());
+        assert!(matches!(
+            check_palette(Some("#ff0000"), Some(&palette[..])),
+            Err(Error::ColorNotInPalette(color)) if color == "#ff0000"
+        ));
+    }
+
+    #[test]
+    fn check_bounded_window_rejects_long_windows() {
+        let config = Config {
+            max_window_seconds: 100,
+            ..Default::default()
+        };
+        assert!(check_bounded_window(&config, 0, 100).is_ok());
+        assert!(matches!(
+            check_bounded_window(&config, 0, 101),
+            Err(Error::WindowTooLong { maximum: 100 })
+        ));
+        assert!(matches!(
+            check_bounded_window(&config, i64::MIN, i64::MAX),
+            Err(Error::WindowTooLong { .. })
+        ));
+        assert!(matches!(
+            check_bounded_window(&config, 100, 0),
+            Err(Error::InvalidWindow)
+        ));
+    }
+}
@@ -0,0 +1,1428 @@
+// These tests send requests through the whole router against a fresh in-memory database.
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    Router,
+};
+use bb8_diesel::DieselConnectionManager;
+use diesel::{sql_types::BigInt, RunQueryDsl, SqliteConnection};
+use diesel_migrations::MigrationHarness;
+use serde_json::{json, Value};
+use std::time::SystemTime;
+use time::macros::time;
+use tower::ServiceExt;
+
+use calendar::{
+    api_route,
+    config::{Config, WorkingHours},
+    MIGRATIONS,
+};
+
+async fn app() -> Router {
+    app_with(Config::default()).await
+}
+
+async fn app_with(config: Config) -> Router {
+    app_with_pool(config).await.0
+}
+
+type SqlitePool = bb8::Pool<DieselConnectionManager<SqliteConnection>>;
+
+// Also returns the pool for tests which have to change rows in ways the API doesn't allow.
+async fn app_with_pool(config: Config) -> (Router, SqlitePool) {
+    // Every connection to `:memory:` opens a database of its own, so the pool may only hold one.
+    let manager = DieselConnectionManager::<SqliteConnection>::new(":memory:");
+    let pool = bb8::Pool::builder()
+        .max_size(1)
+        .build(manager)
+        .await
+        .expect("Failed to build sqlite pool");
+    pool.get()
+        .await
+        .expect("Failed to get a database connection")
+        .run_pending_migrations(MIGRATIONS)
+        .expect("Failed to run migrations");
+
+    let app = api_route(pool.clone(), config)
+        .await
+        .expect("Failed to build router");
+    (app, pool)
+}
+
+// Events are always created now, tests about their age move them into the past.
+async fn set_created_at(pool: &SqlitePool, id: &Value, created_at: i64) {
+    let mut conn = pool
+        .get()
+        .await
+        .expect("Failed to get a database connection");
+    diesel::sql_query("UPDATE events SET created_at = ? WHERE id = ?")
+        .bind::<BigInt, _>(created_at)
+        .bind::<BigInt, _>(id.as_i64().expect("id is not a number"))
+        .execute(&mut *conn)
+        .expect("Failed to update created_at");
+}
+
+fn now() -> i64 {
+    SystemTime::UNIX_EPOCH.elapsed().unwrap().as_secs() as i64
+}
+
+async fn send(app: &Router, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    let request = Request::builder().method(method).uri(uri);
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .expect("Failed to build request");
+
+    let (status, _, body) = send_request(app, request).await;
+    if body.is_empty() {
+        return (status, Value::Null);
+    }
+
+    (
+        status,
+        serde_json::from_str(&body).expect("Response is not JSON"),
+    )
+}
+
+// Returns the headers as well as the body as text, for responses which aren't JSON or whose
+// headers matter.
+async fn send_request(app: &Router, request: Request<Body>) -> (StatusCode, HeaderMap, String) {
+    let response = app.clone().oneshot(request).await.expect("Request failed");
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .expect("Failed to read response body");
+
+    (
+        status,
+        headers,
+        String::from_utf8(body.to_vec()).expect("Response is not UTF-8"),
+    )
+}
+
+async fn get_text(app: &Router, uri: &str) -> (StatusCode, HeaderMap, String) {
+    let request = Request::builder()
+        .uri(uri)
+        .body(Body::empty())
+        .expect("Failed to build request");
+    send_request(app, request).await
+}
+
+async fn post_event(app: &Router, event: Value) -> Value {
+    let (status, event) = send(app, Method::POST, "/api/event", Some(event)).await;
+    assert_eq!(status, StatusCode::OK, "{event}");
+    event
+}
+
+#[tokio::test]
+async fn put_sets_edited_at() {
+    let app = app().await;
+    let event = post_event(
+        &app,
+        json!({
+            "title": "Hike",
+            "color": "#87d45d",
+            "start_date": 1691226000,
+            "end_date": 1691830800,
+        }),
+    )
+    .await;
+    assert_eq!(event["edited_at"], Value::Null);
+
+    let uri = format!("/api/event/{}", event["id"]);
+    let (status, edited) = send(
+        &app,
+        Method::PUT,
+        &uri,
+        Some(json!({ "title": "Long hike" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{edited}");
+    assert_eq!(edited["title"], "Long hike");
+
+    let created_at = edited["created_at"]
+        .as_i64()
+        .expect("created_at is missing");
+    let edited_at = edited["edited_at"].as_i64().expect("edited_at is not set");
+    assert!(edited_at >= created_at);
+}
+
+#[tokio::test]
+async fn get_by_id_includes_deleted_events_on_request() {
+    let app = app().await;
+    let event = post_event(
+        &app,
+        json!({
+            "title": "Cancelled",
+            "color": "#87d45d",
+            "start_date": 1691226000,
+            "end_date": 1691229600,
+        }),
+    )
+    .await;
+    let uri = format!("/api/event/{}", event["id"]);
+    let (status, _) = send(&app, Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = send(&app, Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, deleted) = send(
+        &app,
+        Method::GET,
+        &format!("{uri}?includeDeleted=true"),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{deleted}");
+    assert_eq!(deleted["id"], event["id"]);
+    assert!(deleted["deleted_at"].is_i64());
+}
+
+async fn post_user(app: &Router, username: &str) {
+    let (status, user) = send(
+        app,
+        Method::POST,
+        "/api/user",
+        Some(json!({ "username": username })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{user}");
+}
+
+fn owned_event(owner: Option<&str>, start_date: i64) -> Value {
+    event_between(owner, start_date, start_date + 3600)
+}
+
+fn event_between(owner: Option<&str>, start_date: i64, end_date: i64) -> Value {
+    json!({
+        "title": "Meeting",
+        "color": "#87d45d",
+        "start_date": start_date,
+        "end_date": end_date,
+        "owner": owner,
+    })
+}
+
+#[tokio::test]
+async fn users_include_event_counts_on_request() {
+    let app = app().await;
+    post_user(&app, "alice").await;
+    post_user(&app, "bob").await;
+    post_event(&app, owned_event(Some("alice"), 1691226000)).await;
+    post_event(&app, owned_event(Some("alice"), 1691236000)).await;
+    post_event(&app, owned_event(Some("bob"), 1691246000)).await;
+    post_event(&app, owned_event(None, 1691256000)).await;
+
+    let (status, users) = send(&app, Method::GET, "/api/user", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(users[0].get("eventCount").is_none());
+
+    let (status, users) = send(&app, Method::GET, "/api/user?includeEventCount=true", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let mut counts: Vec<(&str, i64)> = users
+        .as_array()
+        .expect("Users are not a list")
+        .iter()
+        .map(|user| {
+            (
+                user["username"].as_str().unwrap(),
+                user["eventCount"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    counts.sort();
+    assert_eq!(counts, [("alice", 2), ("bob", 1)]);
+
+    let (status, bob) = send(
+        &app,
+        Method::GET,
+        "/api/user/bob?includeEventCount=true",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(bob["eventCount"], 1);
+}
+
+#[tokio::test]
+async fn events_can_be_filtered_by_owners() {
+    let app = app().await;
+    for username in ["alice", "bob", "carol"] {
+        post_user(&app, username).await;
+    }
+    let alice = post_event(&app, owned_event(Some("alice"), 1691226000)).await;
+    let bob = post_event(&app, owned_event(Some("bob"), 1691236000)).await;
+    post_event(&app, owned_event(Some("bob"), 1692226000)).await;
+    post_event(&app, owned_event(Some("carol"), 1691246000)).await;
+    post_event(&app, owned_event(None, 1691256000)).await;
+
+    let uri = "/api/event?owners=alice,Bob,dave&from=1691200000&to=1691300000";
+    let (status, events) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{events}");
+    let ids: Vec<&Value> = events
+        .as_array()
+        .expect("Events are not a list")
+        .iter()
+        .map(|event| &event["id"])
+        .collect();
+    assert_eq!(ids, [&alice["id"], &bob["id"]]);
+}
+
+#[tokio::test]
+async fn events_are_counted_by_owner() {
+    let app = app().await;
+    post_user(&app, "alice").await;
+    post_user(&app, "bob").await;
+    post_event(&app, owned_event(Some("alice"), 1691226000)).await;
+    post_event(&app, owned_event(Some("bob"), 1691236000)).await;
+    post_event(&app, owned_event(Some("bob"), 1691246000)).await;
+    post_event(&app, owned_event(Some("alice"), 1692226000)).await;
+    post_event(&app, owned_event(None, 1691256000)).await;
+
+    let uri = "/api/event/by-owner?from=1691200000&to=1691300000";
+    let (status, counts) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{counts}");
+    assert_eq!(
+        counts,
+        json!([
+            { "owner": "bob", "count": 2 },
+            { "owner": null, "count": 1 },
+            { "owner": "alice", "count": 1 },
+        ])
+    );
+}
+
+#[tokio::test]
+async fn user_events_are_exported_as_csv() {
+    let app = app().await;
+    post_user(&app, "alice").await;
+    post_user(&app, "bob").await;
+    let event = post_event(&app, owned_event(Some("alice"), 1691226000)).await;
+    post_event(&app, owned_event(Some("bob"), 1691236000)).await;
+    let mut draft = owned_event(Some("alice"), 1691246000);
+    draft["draft"] = json!(true);
+    post_event(&app, draft).await;
+    let deleted = post_event(&app, owned_event(Some("alice"), 1691256000)).await;
+    let uri = format!("/api/event/{}?requester=alice", deleted["id"]);
+    let (status, _) = send(&app, Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, headers, csv) = get_text(&app, "/api/user/Alice/events.csv").await;
+    assert_eq!(status, StatusCode::OK, "{csv}");
+    assert_eq!(headers[header::CONTENT_TYPE], "text/csv; charset=utf-8");
+    let rows: Vec<&str> = csv.lines().collect();
+    assert_eq!(rows.len(), 2, "{csv}");
+    assert!(rows[0].starts_with("id,title,description,color,"));
+    assert!(rows[1].starts_with(&format!("{},Meeting,,#87d45d,1691226000,", event["id"])));
+    assert!(rows[1].contains(",alice,"));
+}
+
+#[tokio::test]
+async fn user_events_csv_has_only_a_header_without_events() {
+    let app = app().await;
+    post_user(&app, "alice").await;
+
+    let (status, _, csv) = get_text(&app, "/api/user/alice/events.csv").await;
+    assert_eq!(status, StatusCode::OK, "{csv}");
+    assert_eq!(csv.lines().count(), 1);
+    assert!(csv.starts_with("id,title,"));
+
+    let (status, _, _) = get_text(&app, "/api/user/dave/events.csv").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn gaps_are_returned_between_events() {
+    let app = app().await;
+    post_user(&app, "alice").await;
+    post_user(&app, "bob").await;
+    post_event(&app, owned_event(Some("alice"), 1691226000)).await;
+    post_event(&app, owned_event(Some("bob"), 1691229600)).await;
+    post_event(&app, owned_event(Some("alice"), 1691236800)).await;
+
+    let uri = "/api/event/gaps?from=1691200000&to=1691300000&owner=alice";
+    let (status, gaps) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{gaps}");
+    assert_eq!(
+        gaps,
+        json!([{ "start": 1691229600, "end": 1691236800, "durationSeconds": 7200 }])
+    );
+
+    // Bob's event starts right when the first one ends, which is not a gap.
+    let uri = "/api/event/gaps?from=1691200000&to=1691300000";
+    let (status, gaps) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{gaps}");
+    assert_eq!(
+        gaps,
+        json!([{ "start": 1691233200, "end": 1691236800, "durationSeconds": 3600 }])
+    );
+}
+
+#[tokio::test]
+async fn total_time_coalesces_overlapping_events() {
+    let app = app().await;
+    post_user(&app, "alice").await;
+    post_user(&app, "bob").await;
+    post_event(&app, owned_event(Some("alice"), 1691226000)).await;
+    post_event(&app, owned_event(Some("alice"), 1691227800)).await;
+    post_event(&app, owned_event(Some("bob"), 1691233200)).await;
+
+    let uri = "/api/event/total-time?from=1691200000&to=1691300000&owner=alice";
+    let (status, total) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{total}");
+    assert_eq!(total, json!({ "totalSeconds": 5400, "rawSeconds": 7200 }));
+
+    // The first event is clipped to the window which starts 15 minutes into it.
+    let uri = "/api/event/total-time?from=1691226900&to=1691300000";
+    let (status, total) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{total}");
+    assert_eq!(total, json!({ "totalSeconds": 8100, "rawSeconds": 9900 }));
+}
+
+#[tokio::test]
+async fn extremes_are_the_longest_and_shortest_events() {
+    let app = app().await;
+    post_user(&app, "alice").await;
+    post_user(&app, "bob").await;
+    post_event(&app, event_between(Some("alice"), 1691226000, 1691229600)).await;
+    let longest = post_event(&app, event_between(Some("alice"), 1691236800, 1691247600)).await;
+    let shortest = post_event(&app, event_between(Some("alice"), 1691250000, 1691251800)).await;
+    post_event(&app, event_between(Some("bob"), 1691226000, 1691262000)).await;
+
+    let uri = "/api/event/extremes?from=1691200000&to=1691300000&owner=alice";
+    let (status, extremes) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{extremes}");
+    assert_eq!(extremes["longest"]["id"], longest["id"]);
+    assert_eq!(extremes["longest"]["durationSeconds"], 10800);
+    assert_eq!(extremes["shortest"]["id"], shortest["id"]);
+    assert_eq!(extremes["shortest"]["durationSeconds"], 1800);
+
+    let uri = "/api/event/extremes?from=1691300000&to=1691400000";
+    let (status, extremes) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{extremes}");
+    assert_eq!(extremes, json!({ "longest": null, "shortest": null }));
+}
+
+#[tokio::test]
+async fn first_slot_is_after_the_blocking_event() {
+    let app = app().await;
+    post_user(&app, "alice").await;
+    post_user(&app, "bob").await;
+
+    // Started the day before, its occurrence takes up the first two hours of the window.
+    let mut daily = event_between(Some("alice"), 1691139600, 1691146800);
+    daily["recurrence"] = json!("FREQ=DAILY");
+    post_event(&app, daily).await;
+    post_event(&app, owned_event(Some("bob"), 1691233200)).await;
+
+    let uri =
+        "/api/event/first-slot?from=1691226000&to=1691236800&durationSeconds=3600&owner=alice";
+    let (status, slot) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{slot}");
+    assert_eq!(
+        slot,
+        json!({ "start": 1691233200, "end": 1691236800, "durationSeconds": 3600 })
+    );
+
+    // Bob's event takes up the last free hour.
+    let uri = "/api/event/first-slot?from=1691226000&to=1691236800&durationSeconds=3600";
+    let (status, _) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn busy_hours_count_event_starts_per_hour() {
+    let app = app().await;
+    post_user(&app, "alice").await;
+    post_user(&app, "bob").await;
+
+    post_event(&app, owned_event(Some("alice"), 1691226000)).await;
+    post_event(&app, owned_event(Some("alice"), 1691229600)).await;
+    post_event(&app, owned_event(Some("alice"), 1691312400)).await;
+    post_event(&app, owned_event(Some("bob"), 1691226000)).await;
+
+    let uri = "/api/user/alice/busy-hours?from=1691222400&to=1691395200";
+    let (status, busy) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{busy}");
+    let mut counts = vec![0; 24];
+    counts[9] = 2;
+    counts[10] = 1;
+    assert_eq!(busy, json!({ "counts": counts }));
+
+    // Oslo is two hours ahead of UTC in August.
+    let (status, busy) = send(&app, Method::GET, &format!("{uri}&tz=Europe/Oslo"), None).await;
+    assert_eq!(status, StatusCode::OK, "{busy}");
+    let mut counts = vec![0; 24];
+    counts[11] = 2;
+    counts[12] = 1;
+    assert_eq!(busy, json!({ "counts": counts }));
+
+    let uri = "/api/user/carol/busy-hours?from=1691222400&to=1691395200";
+    let (status, _) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn transfer_into_a_conflicting_slot_is_rejected() {
+    let app = app().await;
+    post_user(&app, "alice").await;
+    post_user(&app, "bob").await;
+
+    let event = post_event(&app, owned_event(Some("alice"), 1691226000)).await;
+    let blocking = post_event(&app, event_between(Some("bob"), 1691229000, 1691232600)).await;
+    post_event(&app, owned_event(Some("alice"), 1691229000)).await;
+
+    let uri = format!("/api/event/{}/transfer?checkConflicts=true", event["id"]);
+    let (status, problem) = send(&app, Method::POST, &uri, Some(json!({ "owner": "bob" }))).await;
+    assert_eq!(status, StatusCode::CONFLICT, "{problem}");
+    assert_eq!(problem["error"], "OwnerConflict");
+    assert_eq!(problem["conflicts"], json!([blocking["id"]]));
+
+    let (_, unchanged) = send(
+        &app,
+        Method::GET,
+        &format!("/api/event/{}", event["id"]),
+        None,
+    )
+    .await;
+    assert_eq!(unchanged["owner"], "alice");
+
+    // Without the check the event is moved regardless.
+    let uri = format!("/api/event/{}/transfer", event["id"]);
+    let (status, moved) = send(&app, Method::POST, &uri, Some(json!({ "owner": "BOB" }))).await;
+    assert_eq!(status, StatusCode::OK, "{moved}");
+    assert_eq!(moved["owner"], "bob");
+    assert_eq!(moved["version"], event["version"].as_i64().unwrap() + 1);
+}
+
+#[tokio::test]
+async fn deleted_users_are_hidden_unless_requested() {
+    let app = app().await;
+    post_user(&app, "alice").await;
+    post_user(&app, "bob").await;
+    let event = post_event(&app, owned_event(Some("alice"), 1691226000)).await;
+
+    let (status, _) = send(&app, Method::DELETE, "/api/user/alice", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, Method::DELETE, "/api/user/alice", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, users) = send(&app, Method::GET, "/api/user", None).await;
+    assert_eq!(users.as_array().unwrap().len(), 1);
+    assert_eq!(users[0]["username"], "bob");
+    let (status, _) = send(&app, Method::GET, "/api/user/alice", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, users) = send(&app, Method::GET, "/api/user?includeDeleted=true", None).await;
+    assert_eq!(users.as_array().unwrap().len(), 2);
+    let (status, alice) = send(
+        &app,
+        Method::GET,
+        "/api/user/alice?includeDeleted=true",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{alice}");
+    assert!(alice["deletedAt"].is_i64());
+
+    // Their events stay attributed to them.
+    let (_, kept) = send(
+        &app,
+        Method::GET,
+        &format!("/api/event/{}", event["id"]),
+        None,
+    )
+    .await;
+    assert_eq!(kept["owner"], "alice");
+}
+
+#[tokio::test]
+async fn heatmap_uses_the_default_timezone_without_tz() {
+    let app = app_with(Config {
+        default_timezone: Some("Europe/Oslo".to_string()),
+        ..Config::default()
+    })
+    .await;
+    post_event(&app, owned_event(None, 1691226000)).await;
+
+    // Saturday 09:00 UTC is 11:00 in Oslo, an explicit tz still wins over the default.
+    let uri = "/api/event/heatmap?from=1691222400&to=1691308800";
+    let (status, heatmap) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{heatmap}");
+    assert_eq!(heatmap["counts"][5][11], 1);
+    assert_eq!(heatmap["counts"][5][9], 0);
+
+    let (_, heatmap) = send(&app, Method::GET, &format!("{uri}&tz=UTC"), None).await;
+    assert_eq!(heatmap["counts"][5][9], 1);
+    assert_eq!(heatmap["counts"][5][11], 0);
+}
+
+#[tokio::test]
+async fn etag_is_stable_until_the_event_changes() {
+    let app = app().await;
+    let event = post_event(&app, owned_event(None, 1691226000)).await;
+    let uri = format!("/api/event/{}", event["id"]);
+
+    let (status, headers, _) = get_text(&app, &uri).await;
+    assert_eq!(status, StatusCode::OK);
+    let etag = headers[header::ETAG].clone();
+    let (_, headers, _) = get_text(&app, &uri).await;
+    assert_eq!(headers[header::ETAG], etag);
+
+    // The JSON-LD representation has a tag of its own.
+    let (_, headers, _) = get_text(&app, &format!("{uri}?format=jsonld")).await;
+    assert_ne!(headers[header::ETAG], etag);
+
+    let (status, _) = send(&app, Method::PUT, &uri, Some(json!({ "title": "Moved" }))).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, headers, _) = get_text(&app, &uri).await;
+    assert_ne!(headers[header::ETAG], etag);
+}
+
+#[tokio::test]
+async fn put_creates_missing_events_but_not_deleted_ones() {
+    let app = app().await;
+    let event = json!({
+        "title": "Hike",
+        "color": "#87d45d",
+        "start_date": 1691226000,
+        "end_date": 1691229600,
+    });
+
+    let (status, created) = send(&app, Method::PUT, "/api/event/42", Some(event.clone())).await;
+    assert_eq!(status, StatusCode::CREATED, "{created}");
+    assert_eq!(created["id"], 42);
+
+    let (status, _) = send(&app, Method::DELETE, "/api/event/42", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(&app, Method::PUT, "/api/event/42", Some(event)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(&app, Method::DELETE, "/api/event/42", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send(&app, Method::DELETE, "/api/event/43", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn lists_over_the_soft_limit_are_truncated() {
+    let app = app_with(Config {
+        result_soft_limit: Some(2),
+        ..Config::default()
+    })
+    .await;
+    for hour in 0..3 {
+        post_event(&app, owned_event(None, 1691226000 + hour * 3600)).await;
+    }
+
+    let (status, headers, body) = get_text(&app, "/api/event").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let events: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(events.as_array().unwrap().len(), 2);
+    assert_eq!(headers["x-result-truncated"], "true");
+    assert_eq!(headers["x-total-count"], "3");
+
+    // A page smaller than the soft limit still matches too many events.
+    let (_, headers, body) = get_text(&app, "/api/event?limit=1").await;
+    let events: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(events.as_array().unwrap().len(), 1);
+    assert_eq!(headers["x-total-count"], "3");
+
+    let (_, headers, _) = get_text(&app, "/api/event?from=1691226000&to=1691233200").await;
+    assert!(!headers.contains_key("x-result-truncated"));
+}
+
+async fn put_if_match(app: &Router, uri: &str, if_match: &str, body: Value) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(Method::PUT)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::IF_MATCH, if_match)
+        .body(Body::from(body.to_string()))
+        .expect("Failed to build request");
+    let (status, _, body) = send_request(app, request).await;
+    (
+        status,
+        serde_json::from_str(&body).expect("Response is not JSON"),
+    )
+}
+
+#[tokio::test]
+async fn put_increments_the_version_and_rejects_stale_ones() {
+    let app = app().await;
+    let event = post_event(&app, owned_event(None, 1691226000)).await;
+    assert_eq!(event["version"], 1);
+    let uri = format!("/api/event/{}", event["id"]);
+
+    let (status, edited) = put_if_match(&app, &uri, "\"1\"", json!({ "title": "One" })).await;
+    assert_eq!(status, StatusCode::OK, "{edited}");
+    assert_eq!(edited["version"], 2);
+    let (_, edited) = send(&app, Method::PUT, &uri, Some(json!({ "title": "Two" }))).await;
+    assert_eq!(edited["version"], 3);
+
+    let (status, problem) = put_if_match(&app, &uri, "\"2\"", json!({ "title": "Stale" })).await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED, "{problem}");
+
+    // `*` only asks for the event to exist.
+    let (status, edited) = put_if_match(&app, &uri, "*", json!({ "title": "Any" })).await;
+    assert_eq!(status, StatusCode::OK, "{edited}");
+    assert_eq!(edited["version"], 4);
+    let (status, _) = put_if_match(&app, "/api/event/99", "*", json!({ "title": "New" })).await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+}
+
+#[tokio::test]
+async fn heatmap_counts_every_hour_an_event_spans() {
+    let app = app().await;
+    // Saturday from 09:00 to 11:00 UTC and Sunday at 23:00 UTC, which is Monday in Oslo.
+    post_event(&app, event_between(None, 1691226000, 1691233200)).await;
+    post_event(&app, owned_event(None, 1691362800)).await;
+
+    let uri = "/api/event/heatmap?from=1691193600&to=1691452800";
+    let (status, heatmap) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{heatmap}");
+    let mut counts = vec![vec![0; 24]; 7];
+    counts[5][9] = 1;
+    counts[5][10] = 1;
+    counts[6][23] = 1;
+    assert_eq!(heatmap, json!({ "counts": counts }));
+
+    let (status, heatmap) = send(&app, Method::GET, &format!("{uri}&tz=Europe/Oslo"), None).await;
+    assert_eq!(status, StatusCode::OK, "{heatmap}");
+    let mut counts = vec![vec![0; 24]; 7];
+    counts[5][11] = 1;
+    counts[5][12] = 1;
+    counts[0][1] = 1;
+    assert_eq!(heatmap, json!({ "counts": counts }));
+}
+
+#[tokio::test]
+async fn errors_are_problem_details() {
+    let app = app().await;
+
+    let (status, headers, body) = get_text(&app, "/api/event/999").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(headers[header::CONTENT_TYPE], "application/problem+json");
+    let problem: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(problem["type"], "/problems/not-found");
+    assert_eq!(problem["title"], "Not Found");
+    assert_eq!(problem["status"], 404);
+    assert_eq!(problem["error"], "NotFound");
+    assert_eq!(problem["instance"], "/api/event/999");
+
+    let mut event = owned_event(None, 1691226000);
+    event["color"] = json!("green");
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/api/event")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(event.to_string()))
+        .unwrap();
+    let (status, headers, body) = send_request(&app, request).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(headers[header::CONTENT_TYPE], "application/problem+json");
+    let problem: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(problem["type"], "/problems/invalid-color");
+    assert_eq!(problem["title"], "Bad Request");
+    assert_eq!(problem["status"], 400);
+    assert_eq!(problem["error"], "InvalidColor");
+    assert_eq!(
+        problem["detail"],
+        "green is not a valid hex color like #87d45d"
+    );
+    assert_eq!(problem["instance"], "/api/event");
+}
+
+#[tokio::test]
+async fn bodies_with_the_wrong_content_type_are_unsupported() {
+    let app = app().await;
+    let body = owned_event(None, 1691226000).to_string();
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/api/event")
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from(body))
+        .unwrap();
+    let (status, _, body) = send_request(&app, request).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE, "{body}");
+    let problem: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(problem["error"], "UnsupportedMediaType");
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/api/event/ics/validate")
+        .header(header::CONTENT_TYPE, "text/plain")
+        .body(Body::from("BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n"))
+        .unwrap();
+    let (status, _, body) = send_request(&app, request).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE, "{body}");
+}
+
+#[tokio::test]
+async fn overlaps_are_counted_for_every_event() {
+    let app = app().await;
+    // Three events overlapping each other and one on its own.
+    post_event(&app, event_between(None, 1691226000, 1691236800)).await;
+    post_event(&app, event_between(None, 1691229600, 1691240400)).await;
+    post_event(&app, event_between(None, 1691233200, 1691244000)).await;
+    post_event(&app, event_between(None, 1691250000, 1691253600)).await;
+
+    let uri = "/api/event/overlaps?from=1691193600&to=1691280000";
+    let (status, events) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{events}");
+    let counts: Vec<&Value> = events
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| &event["overlapCount"])
+        .collect();
+    assert_eq!(counts, [&json!(2), &json!(2), &json!(2), &json!(0)]);
+}
+
+#[tokio::test]
+async fn events_are_sorted_by_priority_then_start_date() {
+    let app = app().await;
+    let mut ids = Vec::new();
+    for (hour, priority) in [(0, None), (1, Some(5)), (2, Some(5)), (3, Some(-1))] {
+        let event = post_event(&app, owned_event(None, 1691226000 + hour * 3600)).await;
+        if let Some(priority) = priority {
+            let uri = format!("/api/event/{}/priority", event["id"]);
+            let (status, event) = send(
+                &app,
+                Method::PUT,
+                &uri,
+                Some(json!({ "priority": priority })),
+            )
+            .await;
+            assert_eq!(status, StatusCode::OK, "{event}");
+        }
+        ids.push(event["id"].clone());
+    }
+
+    // Events without a priority have the default of 0, ties keep the order of their start date.
+    let (status, events) = send(&app, Method::GET, "/api/event?sort=priority", None).await;
+    assert_eq!(status, StatusCode::OK, "{events}");
+    let sorted: Vec<&Value> = events
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| &event["id"])
+        .collect();
+    assert_eq!(sorted, [&ids[1], &ids[2], &ids[0], &ids[3]]);
+
+    let uri = format!("/api/event/{}/priority", ids[0]);
+    let (status, _) = send(&app, Method::PUT, &uri, Some(json!({ "priority": 101 }))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn events_can_be_returned_for_fullcalendar() {
+    let app = app().await;
+    let event = post_event(&app, owned_event(None, 1691226000)).await;
+
+    let (status, events) = send(&app, Method::GET, "/api/event?format=fullcalendar", None).await;
+    assert_eq!(status, StatusCode::OK, "{events}");
+    assert_eq!(
+        events,
+        json!([{
+            "id": event["id"],
+            "title": "Meeting",
+            "start": "2023-08-05T09:00:00Z",
+            "end": "2023-08-05T10:00:00Z",
+            "color": "#87d45d",
+            "allDay": false,
+        }])
+    );
+}
+
+#[tokio::test]
+async fn head_returns_the_headers_of_get_without_a_body() {
+    let app = app().await;
+    let event = post_event(&app, owned_event(None, 1691226000)).await;
+    let uri = format!("/api/event/{}", event["id"]);
+
+    let (_, get_headers, _) = get_text(&app, &uri).await;
+    let request = Request::builder()
+        .method(Method::HEAD)
+        .uri(&uri)
+        .body(Body::empty())
+        .unwrap();
+    let (status, headers, body) = send_request(&app, request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.is_empty());
+    assert_eq!(headers[header::ETAG], get_headers[header::ETAG]);
+    assert_eq!(
+        headers[header::CONTENT_TYPE],
+        get_headers[header::CONTENT_TYPE]
+    );
+
+    let request = Request::builder()
+        .method(Method::HEAD)
+        .uri("/api/event/999")
+        .body(Body::empty())
+        .unwrap();
+    let (status, _, body) = send_request(&app, request).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body.is_empty());
+}
+
+#[tokio::test]
+async fn first_slot_is_within_working_hours() {
+    // 09:00 to 17:00 in Oslo every day, which is 07:00 to 15:00 UTC in August.
+    let app = app_with(Config {
+        working_hours: Some(WorkingHours {
+            days: [Some((time!(09:00), time!(17:00))); 7],
+            timezone: Some("Europe/Oslo".to_string()),
+        }),
+        ..Config::default()
+    })
+    .await;
+
+    let uri = "/api/event/first-slot?from=1691193600&to=1691366400&durationSeconds=3600";
+    let (status, slot) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{slot}");
+    assert_eq!(slot["start"], 1691218800);
+
+    // Only half an hour is left on Saturday, so the slot moves to the start of Sunday.
+    post_event(&app, event_between(None, 1691218800, 1691245800)).await;
+    let (status, slot) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{slot}");
+    assert_eq!(slot["start"], 1691305200);
+
+    // The evening is free but outside of the working hours.
+    let uri = "/api/event/first-slot?from=1691247600&to=1691280000&durationSeconds=3600";
+    let (status, _) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn events_can_be_filtered_by_how_recently_they_were_created() {
+    let (app, pool) = app_with_pool(Config::default()).await;
+    let old = post_event(&app, owned_event(None, 1691226000)).await;
+    let new = post_event(&app, owned_event(None, 1691229600)).await;
+    set_created_at(&pool, &old["id"], now() - 2 * 86400).await;
+
+    let uri = "/api/event?createdWithinSeconds=86400&idsOnly=true";
+    let (status, ids) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{ids}");
+    assert_eq!(ids, json!([new["id"]]));
+}
+
+#[tokio::test]
+async fn events_can_be_filtered_by_duration() {
+    let app = app().await;
+    let short = post_event(&app, event_between(None, 1691226000, 1691227800)).await;
+    let hour = post_event(&app, owned_event(None, 1691229600)).await;
+    let long = post_event(&app, event_between(None, 1691233200, 1691247600)).await;
+
+    let uri = "/api/event?minDurationSeconds=3600&idsOnly=true";
+    let (status, ids) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{ids}");
+    assert_eq!(ids, json!([hour["id"], long["id"]]));
+
+    let uri = "/api/event?minDurationSeconds=1800&maxDurationSeconds=3600&idsOnly=true";
+    let (_, ids) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(ids, json!([short["id"], hour["id"]]));
+}
+
+#[tokio::test]
+async fn events_can_be_created_after_another_event() {
+    let app = app().await;
+    let reference = post_event(&app, owned_event(None, 1691226000)).await;
+
+    let uri = format!("/api/event/{}/after", reference["id"]);
+    let body = json!({ "gapSeconds": 900, "durationSeconds": 1800, "title": "Coffee" });
+    let (status, event) = send(&app, Method::POST, &uri, Some(body.clone())).await;
+    assert_eq!(status, StatusCode::OK, "{event}");
+    assert_eq!(event["start_date"], 1691229600 + 900);
+    assert_eq!(event["end_date"], 1691229600 + 900 + 1800);
+
+    let (status, _) = send(&app, Method::POST, "/api/event/999/after", Some(body)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn coordinates_are_rounded_to_the_configured_decimals() {
+    let app = app().await;
+    let mut event = owned_event(None, 1691226000);
+    event["location_lat"] = json!(60.123456789);
+    event["location_lng"] = json!(7.987654321);
+    let event = post_event(&app, event).await;
+    assert_eq!(event["location_lat"], 60.123457);
+    assert_eq!(event["location_lng"], 7.987654);
+
+    let app = app_with(Config {
+        coordinate_decimals: 2,
+        ..Config::default()
+    })
+    .await;
+    let mut event = owned_event(None, 1691226000);
+    event["location_lat"] = json!(60.123456789);
+    event["location_lng"] = json!(7.987654321);
+    let event = post_event(&app, event).await;
+    assert_eq!(event["location_lat"], 60.12);
+    assert_eq!(event["location_lng"], 7.99);
+}
+
+#[tokio::test]
+async fn neighbors_are_the_events_before_and_after() {
+    let app = app().await;
+    let first = post_event(&app, owned_event(None, 1691226000)).await;
+    let middle = post_event(&app, owned_event(None, 1691229600)).await;
+    let last = post_event(&app, owned_event(None, 1691233200)).await;
+
+    let uri = format!("/api/event/{}/neighbors", middle["id"]);
+    let (status, neighbors) = send(&app, Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{neighbors}");
+    assert_eq!(neighbors["previous"]["id"], first["id"]);
+    assert_eq!(neighbors["next"]["id"], last["id"]);
+
+    let uri = format!("/api/event/{}/neighbors", first["id"]);
+    let (_, neighbors) = send(&app, Method::GET, &uri, None).await;
+    assert_eq!(neighbors["previous"], Value::Null);
+    assert_eq!(neighbors["next"]["id"], middle["id"]);
+
+    let (status, _) = send(&app, Method::GET, "/api/event/999/neighbors", None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn events_shorter_than_the_minimum_duration_are_rejected() {
+    let app = app_with(Config {
+        min_duration_seconds: Some(300),
+        ..Config::default()
+    })
+    .await;
+
+    let event = event_between(None, 1691226000, 1691226060);
+    let (status, problem) = send(&app, Method::POST, "/api/event", Some(event)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{problem}");
+    assert_eq!(problem["error"], "DurationTooShort");
+    assert_eq!(
+        problem["detail"],
+        "The event lasts 60 seconds but should last at least 300 seconds"
+    );
+
+    let event = post_event(&app, owned_event(None, 1691226000)).await;
+    let uri = format!("/api/event/{}", event["id"]);
+    let (status, _) = send(
+        &app,
+        Method::PUT,
+        &uri,
+        Some(json!({ "end_date": 1691226100 })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn put_creates_and_then_updates_an_event() {
+    let app = app().await;
+    let event = owned_event(None, 1691226000);
+
+    let (status, created) = send(&app, Method::PUT, "/api/event/7", Some(event)).await;
+    assert_eq!(status, StatusCode::CREATED, "{created}");
+    assert_eq!(created["id"], 7);
+    assert_eq!(created["title"], "Meeting");
+
+    let body = json!({ "title": "Standup" });
+    let (status, updated) = send(&app, Method::PUT, "/api/event/7", Some(body)).await;
+    assert_eq!(status, StatusCode::OK, "{updated}");
+    assert_eq!(updated["title"], "Standup");
+    assert_eq!(updated["start_date"], 1691226000);
+
+    // Posted events get ids after the ones picked by clients.
+    let posted = post_event(&app, owned_event(None, 1691229600)).await;
+    assert_eq!(posted["id"], 8);
+}
+
+#[tokio::test]
+async fn events_can_be_returned_as_jsonld() {
+    let app = app().await;
+    let mut event = owned_event(None, 1691226000);
+    event["location_name"] = json!("Hardangervidda");
+    event["location_lat"] = json!(60.052);
+    event["location_lng"] = json!(7.4142);
+    let event = post_event(&app, event).await;
+
+    let uri = format!("/api/event/{}?format=jsonld", event["id"]);
+    let (status, jsonld) = send(&app, Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{jsonld}");
+    assert_eq!(
+        jsonld,
+        json!({
+            "@context": "https://schema.org",
+            "@type": "Event",
+            "name": "Meeting",
+            "startDate": "2023-08-05T09:00:00Z",
+            "endDate": "2023-08-05T10:00:00Z",
+            "location": {
+                "@type": "Place",
+                "name": "Hardangervidda",
+                "geo": {
+                    "@type": "GeoCoordinates",
+                    "latitude": 60.052,
+                    "longitude": 7.4142,
+                },
+            },
+        })
+    );
+}
+
+#[tokio::test]
+async fn events_can_be_filtered_by_weekday() {
+    let app = app().await;
+    let saturday = post_event(&app, owned_event(None, 1691226000)).await;
+    // Saturday 23:30 UTC, which is already Sunday in Oslo.
+    let late = post_event(&app, owned_event(None, 1691278200)).await;
+    let sunday = post_event(&app, owned_event(None, 1691312400)).await;
+
+    let (status, ids) = send(
+        &app,
+        Method::GET,
+        "/api/event?weekday=saturday&idsOnly=true",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{ids}");
+    assert_eq!(ids, json!([saturday["id"], late["id"]]));
+
+    let uri = "/api/event?weekday=sunday&tz=Europe/Oslo&idsOnly=true";
+    let (_, ids) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(ids, json!([late["id"], sunday["id"]]));
+
+    let (status, _) = send(&app, Method::GET, "/api/event?weekday=someday", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn events_are_exported_as_ndjson() {
+    let app = app().await;
+    for hour in 0..3 {
+        post_event(&app, owned_event(None, 1691226000 + hour * 3600)).await;
+    }
+    post_event(&app, owned_event(None, 1691312400)).await;
+
+    let (status, headers, body) =
+        get_text(&app, "/api/event/ndjson?from=1691193600&to=1691280000").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(headers[header::CONTENT_TYPE], "application/x-ndjson");
+    let events: Vec<Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("Line is not JSON"))
+        .collect();
+    assert_eq!(events.len(), 3);
+    assert!(events.iter().all(|event| event["title"] == "Meeting"));
+}
+
+#[tokio::test]
+async fn put_validates_the_merged_event() {
+    let app = app().await;
+    let event = post_event(&app, owned_event(None, 1691226000)).await;
+    let uri = format!("/api/event/{}", event["id"]);
+
+    // Only the start is sent, it would be after the stored end.
+    let body = json!({ "start_date": 1691233200 });
+    let (status, problem) = send(&app, Method::PUT, &uri, Some(body)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{problem}");
+    assert_eq!(problem["error"], "InvalidDateRange");
+
+    let (_, unchanged) = send(&app, Method::GET, &uri, None).await;
+    assert_eq!(unchanged["start_date"], 1691226000);
+}
+
+#[tokio::test]
+async fn events_are_shifted_by_their_own_offsets() {
+    let app = app().await;
+    let first = post_event(&app, owned_event(None, 1691226000)).await;
+    let second = post_event(&app, owned_event(None, 1691229600)).await;
+    let third = post_event(&app, owned_event(None, 1691233200)).await;
+
+    let body = json!([
+        { "id": first["id"], "offsetSeconds": 1800 },
+        { "id": second["id"], "offsetSeconds": -600 },
+        { "id": 999, "offsetSeconds": 60 },
+    ]);
+    let (status, shifted) = send(&app, Method::POST, "/api/event/batch/shift", Some(body)).await;
+    assert_eq!(status, StatusCode::OK, "{shifted}");
+    assert_eq!(shifted["notFound"], json!([999]));
+    let updated = shifted["updated"].as_array().unwrap();
+    assert_eq!(updated.len(), 2);
+    assert_eq!(updated[0]["start_date"], 1691227800);
+    assert_eq!(updated[0]["end_date"], 1691231400);
+    assert_eq!(updated[1]["start_date"], 1691229000);
+    assert!(updated.iter().all(|event| event["edited_at"].is_i64()));
+
+    let (_, untouched) = send(
+        &app,
+        Method::GET,
+        &format!("/api/event/{}", third["id"]),
+        None,
+    )
+    .await;
+    assert_eq!(untouched["start_date"], 1691233200);
+}
+
+#[tokio::test]
+async fn dependencies_are_added_and_their_order_is_checked() {
+    let app = app().await;
+    let prerequisite = post_event(&app, owned_event(None, 1691226000)).await;
+    let dependent = post_event(&app, owned_event(None, 1691229600)).await;
+
+    let uri = format!("/api/event/{}/dependencies", dependent["id"]);
+    let body = json!({ "dependsOn": prerequisite["id"] });
+    let (status, dependencies) = send(&app, Method::POST, &uri, Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED, "{dependencies}");
+    assert_eq!(dependencies["blockedBy"][0]["id"], prerequisite["id"]);
+
+    // The other way around would be a cycle.
+    let uri = format!("/api/event/{}/dependencies", prerequisite["id"]);
+    let body = json!({ "dependsOn": dependent["id"] });
+    let (status, problem) = send(&app, Method::POST, &uri, Some(body)).await;
+    assert_eq!(status, StatusCode::CONFLICT, "{problem}");
+    assert_eq!(problem["error"], "DependencyCycle");
+
+    let uri = format!("/api/event/{}?checkDependencies=true", dependent["id"]);
+    let body = json!({ "start_date": 1691228000, "end_date": 1691231600 });
+    let (status, problem) = send(&app, Method::PUT, &uri, Some(body)).await;
+    assert_eq!(status, StatusCode::CONFLICT, "{problem}");
+    assert_eq!(problem["error"], "DependencyViolation");
+}
+
+#[tokio::test]
+async fn events_are_listed_by_the_day_they_were_created_on() {
+    let (app, pool) = app_with_pool(Config::default()).await;
+    let morning = post_event(&app, owned_event(None, 1691226000)).await;
+    let night = post_event(&app, owned_event(None, 1691229600)).await;
+    let next_day = post_event(&app, owned_event(None, 1691233200)).await;
+    // Saturday 10:00 UTC, Saturday 23:30 UTC which is Sunday in Oslo and Sunday 12:00 UTC.
+    set_created_at(&pool, &morning["id"], 1691229600).await;
+    set_created_at(&pool, &night["id"], 1691278200).await;
+    set_created_at(&pool, &next_day["id"], 1691323200).await;
+
+    let ids = |events: &Value| -> Vec<Value> {
+        events
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|event| event["id"].clone())
+            .collect()
+    };
+
+    let (status, events) = send(
+        &app,
+        Method::GET,
+        "/api/event/created-on?date=2023-08-05",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{events}");
+    assert_eq!(ids(&events), [morning["id"].clone(), night["id"].clone()]);
+
+    let uri = "/api/event/created-on?date=2023-08-06&tz=Europe/Oslo";
+    let (_, events) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(ids(&events), [night["id"].clone(), next_day["id"].clone()]);
+
+    let (status, _) = send(
+        &app,
+        Method::GET,
+        "/api/event/created-on?date=5.8.2023",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn ids_only_returns_the_ids_of_the_matching_events() {
+    let app = app().await;
+    for (hour, title) in [(0, "Hike"), (1, "Lunch"), (2, "Night hike")] {
+        let mut event = owned_event(None, 1691226000 + hour * 3600);
+        event["title"] = json!(title);
+        post_event(&app, event).await;
+    }
+
+    let (status, events) = send(&app, Method::GET, "/api/event?q=hike", None).await;
+    assert_eq!(status, StatusCode::OK, "{events}");
+    let expected: Vec<Value> = events
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["id"].clone())
+        .collect();
+    assert_eq!(expected.len(), 2);
+
+    let (status, ids) = send(&app, Method::GET, "/api/event?q=hike&idsOnly=true", None).await;
+    assert_eq!(status, StatusCode::OK, "{ids}");
+    assert_eq!(ids, Value::Array(expected));
+}
+
+#[tokio::test]
+async fn drafts_are_hidden_until_published() {
+    let app = app().await;
+    let mut draft = owned_event(None, 1691226000);
+    draft["draft"] = json!(true);
+    let draft = post_event(&app, draft).await;
+    let uri = format!("/api/event/{}", draft["id"]);
+
+    let (_, events) = send(&app, Method::GET, "/api/event", None).await;
+    assert_eq!(events, json!([]));
+    let (status, _) = send(&app, Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, events) = send(
+        &app,
+        Method::GET,
+        "/api/event?includeDrafts=true&idsOnly=true",
+        None,
+    )
+    .await;
+    assert_eq!(events, json!([draft["id"]]));
+
+    let (status, published) = send(&app, Method::POST, &format!("{uri}/publish"), None).await;
+    assert_eq!(status, StatusCode::OK, "{published}");
+    assert_eq!(published["draft"], false);
+    let (_, events) = send(&app, Method::GET, "/api/event?idsOnly=true", None).await;
+    assert_eq!(events, json!([draft["id"]]));
+}
+
+#[tokio::test]
+async fn events_beyond_the_horizon_are_rejected() {
+    let app = app_with(Config {
+        max_future_seconds: Some(86400),
+        ..Config::default()
+    })
+    .await;
+
+    let start = now() + 2 * 86400;
+    let (status, problem) = send(
+        &app,
+        Method::POST,
+        "/api/event",
+        Some(owned_event(None, start)),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{problem}");
+    assert_eq!(problem["error"], "TooFarInFuture");
+
+    post_event(&app, owned_event(None, now() + 3600)).await;
+}
+
+#[tokio::test]
+async fn events_can_be_filtered_by_color_group() {
+    let app = app_with(Config {
+        color_groups: [(
+            "work".to_string(),
+            vec!["#0000ff".to_string(), "#3366ff".to_string()],
+        )]
+        .into_iter()
+        .collect(),
+        ..Config::default()
+    })
+    .await;
+    let mut ids = Vec::new();
+    for (hour, color) in [(0, "#0000FF"), (1, "#3366ff"), (2, "#ff0000")] {
+        let mut event = owned_event(None, 1691226000 + hour * 3600);
+        event["color"] = json!(color);
+        ids.push(post_event(&app, event).await["id"].clone());
+    }
+
+    let (status, events) = send(
+        &app,
+        Method::GET,
+        "/api/event?colorGroup=work&idsOnly=true",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{events}");
+    assert_eq!(events, json!([ids[0], ids[1]]));
+
+    let (status, problem) = send(&app, Method::GET, "/api/event?colorGroup=home", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{problem}");
+    assert_eq!(problem["error"], "UnknownColorGroup");
+}
+
+#[tokio::test]
+async fn explain_describes_the_query() {
+    let app = app().await;
+    post_event(&app, owned_event(None, 1691226000)).await;
+
+    let uri = "/api/event?explain=true&q=hike&sort=priority&weekday=monday&limit=5";
+    let (status, explanation) = send(&app, Method::GET, uri, None).await;
+    assert_eq!(status, StatusCode::OK, "{explanation}");
+    assert_eq!(explanation["filters"]["q"], "hike");
+    assert_eq!(explanation["filters"]["sort"], "priority");
+    assert_eq!(explanation["filters"]["weekday"], "monday");
+    assert_eq!(explanation["filters"]["limit"], 5);
+
+    let sql = explanation["sql"].as_str().expect("sql is missing");
+    assert!(sql.contains("LIKE"), "{sql}");
+    assert!(sql.contains("%hike%"), "{sql}");
+    assert!(sql.contains("ORDER BY"), "{sql}");
+    assert!(sql.contains("priority"), "{sql}");
+    assert_eq!(
+        explanation["inMemory"],
+        json!([
+            "keep the events starting on the weekday in the timezone",
+            "skip offset events and keep at most limit events",
+        ])
+    );
+}
+
+#[tokio::test]
+async fn events_are_sorted_by_how_soon_they_are() {
+    let app = app().await;
+    let now = now();
+    let mut ids = Vec::new();
+    for offset in [-86400, -3600, 600, 7200] {
+        ids.push(post_event(&app, owned_event(None, now + offset)).await["id"].clone());
+    }
+
+    let (status, events) = send(
+        &app,
+        Method::GET,
+        "/api/event?sort=soonest&idsOnly=true",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{events}");
+    assert_eq!(events, json!([ids[2], ids[1], ids[3], ids[0]]));
+}
+
+#[tokio::test]
+async fn events_matching_a_posted_filter_are_exported() {
+    let app = app().await;
+    let mut ids = Vec::new();
+    for (start, end, title) in [
+        (1691226000, 1691233200, "Hike"),
+        (1691236800, 1691238600, "Short hike"),
+        (1691240400, 1691247600, "Lunch"),
+    ] {
+        let mut event = event_between(None, start, end);
+        event["title"] = json!(title);
+        ids.push(post_event(&app, event).await["id"].clone());
+    }
+
+    let filter = json!({ "q": "hike", "minDurationSeconds": 3600 });
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/api/event/export?format=csv")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(filter.to_string()))
+        .unwrap();
+    let (status, headers, body) = send_request(&app, request).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert!(headers[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/csv"));
+    let rows: Vec<&str> = body.lines().collect();
+    assert_eq!(rows.len(), 2, "{body}");
+    assert!(rows[0].starts_with("id,title,"));
+    assert!(rows[1].starts_with(&format!("{},Hike,", ids[0])));
+}